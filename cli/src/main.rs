@@ -1,8 +1,16 @@
-use clap::{App, Arg};
+use clap::{App, Arg, ArgMatches, SubCommand};
 use io::Write;
+use quizdown_lib::balance::balance;
+use quizdown_lib::duplicates::find_duplicates;
+use quizdown_lib::lint::{Level, RULES};
+use quizdown_lib::manifest::{BuildStatus, Manifest, DEFAULT_MANIFEST};
+use quizdown_lib::stats::Stats;
 use quizdown_lib::*;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io;
+use std::path::{Path, PathBuf};
+
+mod serve;
 
 fn main() -> Result<(), Error> {
     let args = App::new("quizdown")
@@ -13,21 +21,60 @@ fn main() -> Result<(), Error> {
             Arg::with_name("input")
                 .value_name("INPUT_FILE")
                 .takes_value(true)
-                .help("Input markdown file."),
+                .help("Input markdown file, or a .json quiz (e.g., from --format json)."),
         )
         .arg(
             Arg::with_name("format")
             .long("--format")
                 .short("-f")
                 .value_name("FORMAT")
-                .help("Output format: e.g., html, moodle")
+                .help("Output format: html, snippet, interactive, standalone, moodle or json")
                 .takes_value(true),
         )
         .arg(
             Arg::with_name("name")
             .long("--name")
                 .value_name("QUIZ_NAME")
-                .help("Some formats include the name of the course or quiz; by default this is merely your $INPUT_FILE.")
+                .help("Some formats include the name of the course or quiz; by default this is merely your $INPUT_FILE, or the name in a .json quiz.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("title")
+            .long("--title")
+                .value_name("TITLE")
+                .help("Title shown to students; by default the quiz name.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("category")
+            .long("--category")
+                .value_name("CATEGORY")
+                .help("Moodle question category; by default the quiz name.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("time-limit")
+            .long("--time-limit")
+                .value_name("MINUTES")
+                .help("Time limit, for formats that support one (e.g., QTI).")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("hide-answer-count")
+            .long("--hide-answer-count")
+                .help("In practice pages, use checkboxes even for questions with one correct option."),
+        )
+        .arg(
+            Arg::with_name("balance")
+            .long("--balance")
+                .help("Reorder the options of {reorder} questions to spread correct answers evenly over positions."),
+        )
+        .arg(
+            Arg::with_name("seed")
+            .long("--seed")
+                .value_name("SEED")
+                .default_value("0")
+                .help("With --balance, a number choosing one of many balanced orders; the same seed gives the same order.")
                 .takes_value(true),
         )
         .arg(
@@ -38,25 +85,319 @@ fn main() -> Result<(), Error> {
                 .help("Output file name; otherwise use stdout.")
                 .takes_value(true),
         )
-        .arg(
-            Arg::with_name("theme")
+        .args(&syntax_args())
+        .args(&split_args())
+        .subcommand(
+            SubCommand::with_name("build")
+                .about("Build every quiz listed in a project manifest, skipping unchanged inputs.")
+                .arg(
+                    Arg::with_name("manifest")
+                        .value_name("MANIFEST")
+                        .default_value(DEFAULT_MANIFEST)
+                        .help("A TOML file listing inputs, names, formats and output directories."),
+                )
+                .arg(
+                    Arg::with_name("force")
+                        .long("--force")
+                        .help("Rebuild every output, even if it looks up to date."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("lint")
+                .about("Check questions for likely mistakes; fails if any rule set to deny is broken.")
+                .arg(
+                    Arg::with_name("inputs")
+                        .value_name("INPUT_FILE")
+                        .multiple(true)
+                        .help("Markdown files to check together; by default, every input in the manifest."),
+                )
+                .arg(
+                    Arg::with_name("manifest")
+                        .long("--manifest")
+                        .value_name("MANIFEST")
+                        .default_value(DEFAULT_MANIFEST)
+                        .help("Where to find inputs, config and [lint] levels when no files are given."),
+                )
+                .args(&level_args())
+                .arg(
+                    Arg::with_name("rules")
+                        .long("--rules")
+                        .help("List every rule and its default level."),
+                )
+                .args(&syntax_args())
+                .args(&split_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("duplicates")
+                .about("List questions that are copies, or near-copies, of each other.")
+                .arg(
+                    Arg::with_name("inputs")
+                        .value_name("INPUT_FILE")
+                        .multiple(true)
+                        .help("Markdown files to compare; by default, every input in the manifest."),
+                )
+                .arg(
+                    Arg::with_name("manifest")
+                        .long("--manifest")
+                        .value_name("MANIFEST")
+                        .default_value(DEFAULT_MANIFEST)
+                        .help("Where to find inputs and config when no files are given."),
+                )
+                .arg(
+                    Arg::with_name("threshold")
+                        .long("--threshold")
+                        .value_name("SIMILARITY")
+                        .default_value("0.8")
+                        .help("How similar (0 to 1) two questions must be to be listed."),
+                )
+                .args(&syntax_args())
+                .args(&split_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("stats")
+                .about("Summarize a question bank: question types, options, where correct answers sit, code languages.")
+                .arg(
+                    Arg::with_name("inputs")
+                        .value_name("INPUT_FILE")
+                        .multiple(true)
+                        .help("Markdown files to summarize together; by default, every input in the manifest."),
+                )
+                .arg(
+                    Arg::with_name("manifest")
+                        .long("--manifest")
+                        .value_name("MANIFEST")
+                        .default_value(DEFAULT_MANIFEST)
+                        .help("Where to find inputs and config when no files are given."),
+                )
+                .arg(
+                    Arg::with_name("json")
+                        .long("--json")
+                        .help("Print the summary as JSON."),
+                )
+                .args(&syntax_args())
+                .args(&split_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("schema")
+                .about("Print the JSON Schema that --format json output (and .json input) follows.")
+                .arg(
+                    Arg::with_name("output")
+                        .long("--output")
+                        .short("-o")
+                        .value_name("OUTPUT_FILE")
+                        .help("Output file name; otherwise use stdout.")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("serve")
+                .about("Preview a markdown file in the browser, re-rendering whenever it changes.")
+                .arg(
+                    Arg::with_name("input")
+                        .value_name("INPUT_FILE")
+                        .required_unless("static")
+                        .help("Input markdown file."),
+                )
+                .arg(
+                    Arg::with_name("static")
+                        .long("--static")
+                        .value_name("DIR")
+                        .conflicts_with("input")
+                        .help("Serve the files in DIR as they are instead, e.g., wasm/ for the web editor."),
+                )
+                .arg(
+                    Arg::with_name("name")
+                        .long("--name")
+                        .value_name("QUIZ_NAME")
+                        .help("Name shown in the preview; by default this is merely your $INPUT_FILE.")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("port")
+                        .long("--port")
+                        .short("-p")
+                        .value_name("PORT")
+                        .default_value("8000")
+                        .help("Local port to serve the preview on."),
+                )
+                .args(&syntax_args())
+                .args(&split_args()),
+        )
+        .get_matches();
+
+    if let Some(build_args) = args.subcommand_matches("build") {
+        return build(build_args);
+    }
+    if let Some(duplicates_args) = args.subcommand_matches("duplicates") {
+        return duplicates(duplicates_args);
+    }
+    if let Some(stats_args) = args.subcommand_matches("stats") {
+        return stats(stats_args);
+    }
+    if let Some(lint_args) = args.subcommand_matches("lint") {
+        return lint(lint_args);
+    }
+    if let Some(schema_args) = args.subcommand_matches("schema") {
+        match schema_args.value_of("output") {
+            Some(path) => fs::write(path, json_schema())?,
+            None => println!("{}", json_schema()),
+        }
+        return Ok(());
+    }
+    if let Some(serve_args) = args.subcommand_matches("serve") {
+        let port = serve_args.value_of("port").unwrap_or("8000");
+        if let Some(dir) = serve_args.value_of("static") {
+            return serve::serve_static(dir, port);
+        }
+        let input = serve_args.value_of("input").unwrap();
+        return serve::serve(
+            input,
+            serve_args.value_of("name").unwrap_or(input),
+            port,
+            config_from_args(serve_args),
+        );
+    }
+
+    let config = config_from_args(&args);
+    let stylesheet = config.syntax.stylesheet()?;
+
+    let input = args
+        .value_of("input")
+        .ok_or_else(|| Error::Unexpected("Input file name is required.".to_owned()))?;
+    // read and process ASAP:
+    let mut quiz = Compiler::new(config)?.quiz_from_file(&input, args.value_of("name"))?;
+    // JSON input may carry its own title and category:
+    if let Some(title) = args.value_of("title") {
+        quiz.title = Some(title.to_owned());
+    }
+    if let Some(category) = args.value_of("category") {
+        quiz.category = Some(category.to_owned());
+    }
+    if let Some(minutes) = args.value_of("time-limit") {
+        quiz.time_limit_minutes = Some(minutes.parse().map_err(|_| {
+            Error::Unexpected(format!(
+                "--time-limit must be whole minutes, not {:?}",
+                minutes
+            ))
+        })?);
+    }
+    if args.is_present("hide-answer-count") {
+        quiz.hide_answer_count = true;
+    }
+
+    if args.is_present("balance") {
+        let seed = args.value_of("seed").unwrap_or("0");
+        let seed = seed.parse().map_err(|_| {
+            Error::Unexpected(format!("--seed must be a whole number, not {:?}", seed))
+        })?;
+        eprint!("{}", balance(&mut quiz, seed).to_text());
+    }
+
+    let output_file_name = args.value_of("output").unwrap_or("-");
+    let format: OutputFormat = match args.value_of("format") {
+        None => OutputFormat::from_file_name(output_file_name).ok_or_else(|| {
+            Error::Unexpected(
+                "Must provide a file format (--format=html) or an obvious output file e.g., '.html'"
+                    .to_owned(),
+            )
+        })?,
+        Some(name) => OutputFormat::from_short_name(name)
+            .ok_or_else(|| Error::UnknownFormat(name.to_owned()))?,
+    };
+
+    let output = format.render_with_stylesheet(&quiz, stylesheet.as_deref())?;
+
+    if output_file_name == "-" {
+        println!("{}", output);
+    } else {
+        let mut fp = File::create(output_file_name)?;
+        write!(fp, "{}", output)?;
+        // Fragments have no <head>, so their stylesheet goes alongside.
+        if let Some(css) = stylesheet.filter(|_| !format.is_full_page()) {
+            let css_path = stylesheet_path(Path::new(output_file_name));
+            fs::write(&css_path, css)?;
+            eprintln!("Wrote highlighting stylesheet to {}", css_path.display());
+        }
+    }
+
+    Ok(())
+}
+
+/// Syntax-highlighting flags, shared by the top-level command and ``serve``.
+fn syntax_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("theme")
             .long("--theme")
             .value_name("Syntax Highlighting Theme")
             .help("TODO, list some.")
-            .takes_value(true)
-        )
-        .arg(
-            Arg::with_name("lang")
+            .takes_value(true),
+        Arg::with_name("lang")
             .long("--lang")
             .value_name("Syntax Highlighting language for inline and unmarked code blocks.")
             .help("e.g., java, python, etc.")
+            .takes_value(true),
+        Arg::with_name("syntax-dir")
+            .long("--syntax-dir")
+            .value_name("DIR")
+            .help("A directory of extra .sublime-syntax files; may be repeated.")
             .takes_value(true)
-        )
-        .get_matches();
+            .multiple(true)
+            .number_of_values(1),
+        Arg::with_name("theme-dir")
+            .long("--theme-dir")
+            .value_name("DIR")
+            .help("A directory of extra .tmTheme files; may be repeated.")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1),
+        Arg::with_name("strict")
+            .long("--strict")
+            .help("Fail on code blocks in unknown languages instead of leaving them plain."),
+        Arg::with_name("css-classes")
+            .long("--css-classes")
+            .help("Highlight with CSS classes and a theme stylesheet instead of inline styles."),
+    ]
+}
+
+/// Flags for how markdown becomes questions, shared by the top-level command and ``serve``.
+fn split_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("question-level")
+            .long("--question-level")
+            .value_name("N")
+            .help("Only headings with N #s start questions; those with fewer start sections.")
+            .takes_value(true)
+            .validator(|n| n.parse::<u32>().map(|_| ()).map_err(|e| e.to_string())),
+        Arg::with_name("split-on-rules")
+            .long("--split-on-rules")
+            .help("A --- line also ends a question, so the next one needs no heading."),
+        Arg::with_name("preamble")
+            .long("--preamble")
+            .value_name("WHAT")
+            .help("Markdown before the first question is a question, ignored, or the quiz description.")
+            .takes_value(true)
+            .possible_values(&["question", "ignore", "description"]),
+        Arg::with_name("heading-as-title")
+            .long("--heading-as-title")
+            .help("Use each heading as the question's name instead of showing it in the prompt."),
+    ]
+}
 
+fn config_from_args(args: &ArgMatches) -> Config {
     let mut config = Config::default();
+    let dirs = |name| {
+        args.values_of(name)
+            .map(|dirs| dirs.map(str::to_owned).collect())
+            .unwrap_or_default()
+    };
+    config.syntax.syntax_dirs = dirs("syntax-dir");
+    config.syntax.theme_dirs = dirs("theme-dir");
+    config.syntax.strict = args.is_present("strict");
     if let Some(theme) = args.value_of("theme") {
-        let available_themes = quizdown_lib::list_themes();
+        let available_themes = config
+            .syntax
+            .available_themes()
+            .unwrap_or_else(|_| quizdown_lib::list_themes());
         if available_themes
             .iter()
             .map(|s| s.as_str())
@@ -74,42 +415,151 @@ fn main() -> Result<(), Error> {
     if let Some(lang) = args.value_of("lang") {
         config.syntax.default_lang = lang.to_string();
     }
+    if args.is_present("css-classes") {
+        config.syntax.style = HighlightStyle::Classes;
+    }
+    config.split.question_level = args.value_of("question-level").and_then(|n| n.parse().ok());
+    config.split.rules = args.is_present("split-on-rules");
+    config.heading_as_title = args.is_present("heading-as-title");
+    config.split.preamble = match args.value_of("preamble") {
+        Some("ignore") => Preamble::Ignore,
+        Some("description") => Preamble::Description,
+        _ => Preamble::Question,
+    };
+    config
+}
 
-    let input = args
-        .value_of("input")
-        .expect("Input file name is required.");
-    // read and process ASAP:
-    let questions = process_questions_file(&input, Some(config))?;
-
-    let name: &str = args.value_of("name").unwrap_or(input);
+/// ``--allow RULE``, ``--warn RULE`` and ``--deny RULE``, each of which may be repeated.
+fn level_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    ["allow", "warn", "deny"]
+        .iter()
+        .map(|level| {
+            Arg::with_name(level)
+                .long(level)
+                .value_name("RULE")
+                .help("Change the level of a rule (see --rules); may be repeated.")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+        })
+        .collect()
+}
 
-    let output_file_name = args.value_of("output").unwrap_or("-");
-    let format: OutputFormat = match args.value_of("format") {
+/// ``inputs`` to check together, and the config to read them with: files named on the command line use its flags;
+/// otherwise, the manifest has everything.
+fn inputs_from_args(args: &ArgMatches) -> Result<(Vec<PathBuf>, Config, Option<Manifest>), Error> {
+    Ok(match args.values_of("inputs") {
+        Some(inputs) => (
+            inputs.map(PathBuf::from).collect(),
+            config_from_args(args),
+            None,
+        ),
         None => {
-            if output_file_name.ends_with(".html") {
-                OutputFormat::HtmlFull
-            } else if output_file_name.ends_with(".moodle") {
-                OutputFormat::MoodleXml
-            } else if output_file_name.ends_with(".json") {
-                OutputFormat::JSON
-            } else {
-                panic!("Must provide a file format (--format=html) or an obvious output file e.g., '.html'");
-            }
+            let manifest = Manifest::load(args.value_of("manifest").unwrap_or(DEFAULT_MANIFEST))?;
+            (manifest.inputs(), manifest.config.clone(), Some(manifest))
         }
-        Some("html") => OutputFormat::HtmlFull,
-        Some("json") => OutputFormat::JSON,
-        Some("moodle") => OutputFormat::MoodleXml,
-        Some(other) => panic!("Unknown format '{}'.", other),
-    };
+    })
+}
 
-    let output = format.render(name, &questions)?;
+/// ``quizdown duplicates [INPUT_FILE]...``: list questions that are (nearly) copies of each other.
+fn duplicates(args: &ArgMatches) -> Result<(), Error> {
+    let (paths, config, _) = inputs_from_args(args)?;
+    let threshold: f64 = args
+        .value_of("threshold")
+        .unwrap_or("0.8")
+        .parse()
+        .map_err(|_| Error::Unexpected("--threshold must be a number from 0 to 1".to_owned()))?;
+    let compiler = Compiler::new(config)?;
+    let mut questions = Vec::new();
+    for path in paths {
+        let path = path.to_string_lossy().to_string();
+        for q in compiler.process_file(&path)? {
+            questions.push((path.clone(), q));
+        }
+    }
+    let found = find_duplicates(&questions, threshold);
+    for d in found.iter() {
+        println!("{}", d);
+    }
+    eprintln!("{} possible duplicate(s)", found.len());
+    Ok(())
+}
 
-    if output_file_name == "-" {
-        println!("{}", output);
+/// ``quizdown stats [INPUT_FILE]...``: summarize a question bank as text or JSON.
+fn stats(args: &ArgMatches) -> Result<(), Error> {
+    let (paths, config, _) = inputs_from_args(args)?;
+    let default_lang = config.syntax.default_lang.clone();
+    let compiler = Compiler::new(config)?;
+    let mut questions = Vec::new();
+    for path in paths.iter() {
+        questions.extend(compiler.process_file(&path.to_string_lossy())?);
+    }
+    let stats = Stats::new(&questions, &default_lang);
+    if args.is_present("json") {
+        println!("{}", stats.to_json()?);
     } else {
-        let mut fp = File::create(output_file_name)?;
-        write!(fp, "{}", output)?;
+        println!("Files: {}", paths.len());
+        print!("{}", stats.to_text());
+    }
+    Ok(())
+}
+
+/// ``quizdown lint [INPUT_FILE]...``: report likely mistakes, e.g., duplicate options, with their positions.
+fn lint(args: &ArgMatches) -> Result<(), Error> {
+    if args.is_present("rules") {
+        for rule in RULES {
+            println!(
+                "{:<18} {:<6} {}",
+                rule.name,
+                format!("{:?}", rule.default).to_lowercase(),
+                rule.description
+            );
+        }
+        return Ok(());
+    }
+    let (paths, config, manifest) = inputs_from_args(args)?;
+    let mut options = manifest.map(|m| m.lint).unwrap_or_default();
+    for (name, level) in [
+        ("allow", Level::Allow),
+        ("warn", Level::Warn),
+        ("deny", Level::Deny),
+    ] {
+        for rule in args.values_of(name).into_iter().flatten() {
+            options.levels.insert(rule.to_owned(), level);
+        }
+    }
+
+    let mut sources = Vec::new();
+    for path in paths {
+        let markdown = fs::read_to_string(&path)?;
+        sources.push((path.to_string_lossy().to_string(), markdown));
     }
+    let found = quizdown_lib::lint::lint(&Compiler::new(config)?, &sources, &options)?;
+    for lint in found.iter() {
+        println!("{}", lint);
+    }
+    let errors = found.iter().filter(|l| l.level == Level::Deny).count();
+    eprintln!("{} warning(s), {} error(s)", found.len() - errors, errors);
+    if errors > 0 {
+        return Err(Error::LintFailed(errors));
+    }
+    Ok(())
+}
 
+/// ``quizdown build [MANIFEST]``: render every target in a manifest.
+fn build(args: &ArgMatches) -> Result<(), Error> {
+    let manifest = Manifest::load(args.value_of("manifest").unwrap_or(DEFAULT_MANIFEST))?;
+    let results = manifest.build(args.is_present("force"))?;
+    let mut built = 0;
+    for (target, status) in results.iter() {
+        match status {
+            BuildStatus::Built => {
+                built += 1;
+                eprintln!("built {}", target.output.display());
+            }
+            BuildStatus::UpToDate => eprintln!("up-to-date {}", target.output.display()),
+        }
+    }
+    eprintln!("{} of {} outputs rebuilt.", built, results.len());
     Ok(())
 }
@@ -0,0 +1,265 @@
+use quizdown_lib::html::render_html_preview;
+use quizdown_lib::{Compiler, Config, Error};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
+use tiny_http::{Header, Response, Server};
+
+/// How often we look at the input file for changes.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// The preview page polls this path and reloads when the answer changes.
+const VERSION_PATH: &str = "/__quizdown/version";
+
+/// The latest state of the file being previewed.
+#[derive(Default)]
+struct Preview {
+    /// Bumped on every rebuild, successful or not.
+    version: u64,
+    /// The last page that rendered without errors; kept around so an error doesn't blank the screen.
+    last_good: Option<String>,
+    /// What went wrong with the most recent rebuild, if anything.
+    error: Option<String>,
+}
+
+impl Preview {
+    fn page(&self) -> String {
+        let mut html = self
+            .last_good
+            .clone()
+            .unwrap_or_else(|| "<html><head></head><body></body></html>".to_owned());
+        let mut extra =
+            include_str!("live-reload.html").replace("QUIZDOWN_VERSION", &self.version.to_string());
+        if let Some(err) = &self.error {
+            extra.push_str("<pre class='quizdown-error-overlay'>");
+            extra.push_str(&escape_html(err));
+            extra.push_str("</pre>");
+        }
+        match html.rfind("</body>") {
+            Some(end) => html.insert_str(end, &extra),
+            None => html.push_str(&extra),
+        }
+        html
+    }
+}
+
+/// Enough MIME types for quiz images and the web editor's ``pkg/`` (browsers refuse ``.wasm`` without its own).
+fn content_type(path: &Path) -> &'static str {
+    let ext = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match ext.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css",
+        "js" | "mjs" => "text/javascript",
+        "wasm" => "application/wasm",
+        "json" => "application/json",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "webp" => "image/webp",
+        "txt" | "md" => "text/plain; charset=utf-8",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Undo the ``%20``-style escapes that markdown puts in image URLs.
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut output = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = text.get(i + 1..i + 3).filter(|_| bytes[i] == b'%');
+        match hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+            Some(b) => {
+                output.push(b);
+                i += 3;
+            }
+            None => {
+                output.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&output).into_owned()
+}
+
+/// The file under ``root`` that ``url`` asks for; ``/`` means ``index.html``, and nothing outside ``root`` is served.
+fn static_path(root: &Path, url: &str) -> Option<PathBuf> {
+    let path = url.split(&['?', '#'][..]).next().unwrap_or_default();
+    let path = percent_decode(path.trim_start_matches('/'));
+    let relative = Path::new(if path.is_empty() { "index.html" } else { &path });
+    if !relative
+        .components()
+        .all(|c| matches!(c, Component::Normal(_)))
+    {
+        return None;
+    }
+    Some(root.join(relative)).filter(|p| p.is_file())
+}
+
+fn respond(request: tiny_http::Request, body: Vec<u8>, content_type: &str, status: u16) {
+    let header = Header::from_bytes(&b"Content-Type"[..], content_type.as_bytes()).unwrap();
+    let response = Response::from_data(body)
+        .with_header(header)
+        .with_status_code(status);
+    if let Err(e) = request.respond(response) {
+        eprintln!("Could not respond: {}", e);
+    }
+}
+
+/// Answer with the file ``url`` names under ``root``, or a 404.
+fn respond_with_file(request: tiny_http::Request, root: &Path) {
+    match static_path(root, request.url()).and_then(|p| fs::read(&p).ok().map(|b| (p, b))) {
+        Some((path, body)) => respond(request, body, content_type(&path), 200),
+        None => respond(request, b"Not found".to_vec(), "text/plain", 404),
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn rebuild(
+    preview: &Mutex<Preview>,
+    input: &str,
+    name: &str,
+    compiler: &Compiler,
+    stylesheet: Option<&str>,
+) {
+    let result = compiler
+        .quiz_from_file(input, Some(name))
+        .and_then(|quiz| render_html_preview(&quiz, true, stylesheet));
+    let mut preview = preview.lock().unwrap();
+    preview.version += 1;
+    match result {
+        Ok(html) => {
+            preview.last_good = Some(html);
+            preview.error = None;
+        }
+        Err(e) => {
+            eprintln!("Error in {}: {}", input, e);
+            preview.error = Some(format!("{}: {}", input, e));
+        }
+    }
+}
+
+/// ``quizdown serve INPUT_FILE``: serve a full-page preview of ``input`` on localhost, re-rendering it whenever it changes.
+pub fn serve(input: &str, name: &str, port: &str, config: Config) -> Result<(), Error> {
+    let address = format!("127.0.0.1:{}", port);
+    let server = Server::http(&address)
+        .map_err(|e| Error::Unexpected(format!("could not listen on {}: {}", address, e)))?;
+
+    // Every save is rendered with this one compiler.
+    let stylesheet = config.syntax.stylesheet()?;
+    let compiler = Compiler::new(config)?;
+    let preview = Arc::new(Mutex::new(Preview::default()));
+    let modified = |path: &str| fs::metadata(path).and_then(|m| m.modified()).ok();
+    let mut last_modified: Option<SystemTime> = modified(input);
+    rebuild(&preview, input, name, &compiler, stylesheet.as_deref());
+
+    {
+        let preview = Arc::clone(&preview);
+        let input = input.to_owned();
+        let name = name.to_owned();
+        thread::spawn(move || loop {
+            thread::sleep(POLL_INTERVAL);
+            // Editors often replace the file on save, so it may briefly disappear:
+            let current = match modified(&input) {
+                Some(t) => t,
+                None => continue,
+            };
+            if last_modified != Some(current) {
+                last_modified = Some(current);
+                rebuild(&preview, &input, &name, &compiler, stylesheet.as_deref());
+            }
+        });
+    }
+
+    eprintln!(
+        "Previewing {} at http://{}/ (Ctrl-C to stop)",
+        input, address
+    );
+    // Relative image paths in the preview are resolved against the input's directory:
+    let root = Path::new(input)
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+    let root = if root.as_os_str().is_empty() {
+        PathBuf::from(".")
+    } else {
+        root
+    };
+    for request in server.incoming_requests() {
+        let url = request.url().to_owned();
+        if url == VERSION_PATH {
+            let version = preview.lock().unwrap().version.to_string();
+            respond(request, version.into_bytes(), "text/plain", 200);
+        } else if url == "/" || url.starts_with("/?") {
+            let page = preview.lock().unwrap().page();
+            respond(request, page.into_bytes(), "text/html; charset=utf-8", 200);
+        } else {
+            respond_with_file(request, &root);
+        }
+    }
+    Ok(())
+}
+
+/// ``quizdown serve --static DIR``: serve the files in ``dir`` as they are, e.g., the web editor in ``wasm/``.
+pub fn serve_static(dir: &str, port: &str) -> Result<(), Error> {
+    let address = format!("127.0.0.1:{}", port);
+    let server = Server::http(&address)
+        .map_err(|e| Error::Unexpected(format!("could not listen on {}: {}", address, e)))?;
+    eprintln!("Serving {} at http://{}/ (Ctrl-C to stop)", dir, address);
+    let root = Path::new(dir);
+    for request in server.incoming_requests() {
+        respond_with_file(request, root);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_overlay() {
+        let preview = Preview {
+            version: 7,
+            last_good: Some("<html><body><p>ok</p></body></html>".to_owned()),
+            error: Some("quiz.md: Found no options in <question>.".to_owned()),
+        };
+        let page = preview.page();
+        assert!(page.starts_with("<html><body><p>ok</p>"));
+        assert!(page.ends_with("</body></html>"));
+        assert!(page.contains("var version = \"7\";"));
+        assert!(page.contains(
+            "<pre class='quizdown-error-overlay'>quiz.md: Found no options in &lt;question&gt;.</pre>"
+        ));
+    }
+
+    #[test]
+    fn test_static_path() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        assert_eq!(
+            Some(root.join("src/live-reload.html")),
+            static_path(root, "/src/live-reload.html?v=2")
+        );
+        assert_eq!(
+            Some(root.join("Cargo.toml")),
+            static_path(root, "/Cargo%2Etoml")
+        );
+        assert_eq!(None, static_path(root, "/../Cargo.toml"));
+        assert_eq!(None, static_path(root, "/src/missing.png"));
+        assert_eq!(
+            "application/wasm",
+            content_type(Path::new("pkg/wasm_bg.wasm"))
+        );
+        assert_eq!("big tree.png", percent_decode("big%20tree.png"));
+    }
+}
@@ -0,0 +1,44 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use quizdown_lib::{process_questions_str, Compiler, Config};
+use syntect::highlighting::ThemeSet;
+use syntect::parsing::SyntaxSet;
+
+const QUIZ: &str = r#"
+## What does ``f(3)`` return?
+
+```python
+def f(x):
+    return x * 2
+```
+
+- [ ] 3
+- [x] 6
+- [ ] ``None``
+
+## Which of these are sorting algorithms?
+
+- [x] merge sort
+- [x] ``quicksort``
+- [ ] binary search
+"#;
+
+fn bench_compile(c: &mut Criterion) {
+    // What every call paid before the syntax and theme sets were cached.
+    c.bench_function("reload sets per call", |b| {
+        b.iter(|| {
+            black_box(SyntaxSet::load_defaults_newlines());
+            black_box(ThemeSet::load_defaults());
+            process_questions_str(black_box(QUIZ), None).unwrap()
+        })
+    });
+    c.bench_function("process_questions_str", |b| {
+        b.iter(|| process_questions_str(black_box(QUIZ), None).unwrap())
+    });
+    let compiler = Compiler::new(Config::default()).unwrap();
+    c.bench_function("Compiler::process_str", |b| {
+        b.iter(|| compiler.process_str(black_box(QUIZ)).unwrap())
+    });
+}
+
+criterion_group!(benches, bench_compile);
+criterion_main!(benches);
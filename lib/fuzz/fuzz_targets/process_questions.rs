@@ -0,0 +1,9 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+// Any text should give questions or an ``Error``; a panic here would abort the web editor.
+fuzz_target!(|data: &[u8]| {
+    if let Ok(text) = std::str::from_utf8(data) {
+        let _ = quizdown_lib::process_questions_str(text, None);
+    }
+});
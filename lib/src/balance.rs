@@ -0,0 +1,163 @@
+use crate::stats::position_name;
+use crate::{QOption, Question, Quiz};
+use std::fmt::Write;
+
+/// SplitMix64: a tiny random number generator, so the same seed always gives the same quiz.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+    /// Fisher-Yates.
+    fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            let j = (self.next() % (i as u64 + 1)) as usize;
+            items.swap(i, j);
+        }
+    }
+}
+
+/// What ``balance`` did to a quiz.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BalanceReport {
+    /// How many questions were ``reorderable`` with numbered options.
+    pub questions: usize,
+    /// Correct options at each position (first option first) in those questions, before and after.
+    pub before: Vec<usize>,
+    pub after: Vec<usize>,
+}
+
+impl BalanceReport {
+    pub fn to_text(&self) -> String {
+        let mut output = String::new();
+        writeln!(
+            output,
+            "Balanced {} reorderable question(s); correct answers by position:",
+            self.questions
+        )
+        .unwrap();
+        for i in 0..self.before.len().max(self.after.len()) {
+            let count = |counts: &[usize]| counts.get(i).copied().unwrap_or(0);
+            writeln!(
+                output,
+                "  {}: {} -> {}",
+                position_name(i),
+                count(&self.before),
+                count(&self.after)
+            )
+            .unwrap();
+        }
+        output
+    }
+}
+
+fn count_correct(counts: &mut Vec<usize>, q: &Question) {
+    for (i, opt) in q.options.iter().enumerate() {
+        if opt.correct {
+            if counts.len() <= i {
+                counts.resize(i + 1, 0);
+            }
+            counts[i] += 1;
+        }
+    }
+}
+
+/// Spread correct answers evenly over positions by reordering the options of every ``reorderable`` question with
+/// numbered options; ``pinned`` options stay where they are, and other questions are left alone.
+/// Questions are visited in order, and each puts its correct options in the positions used least so far.
+/// The same quiz and ``seed`` always give the same result.
+pub fn balance(quiz: &mut Quiz, seed: u64) -> BalanceReport {
+    let mut rng = Rng(seed);
+    let mut report = BalanceReport::default();
+    for q in quiz.questions_mut().filter(|q| q.ordered && q.reorderable) {
+        report.questions += 1;
+        count_correct(&mut report.before, q);
+
+        let mut slots: Vec<usize> = (0..q.options.len())
+            .filter(|&i| !q.options[i].pinned)
+            .collect();
+        // Least-used first; the shuffle breaks ties, since the sort is stable.
+        rng.shuffle(&mut slots);
+        slots.sort_by_key(|&i| report.after.get(i).copied().unwrap_or(0));
+
+        let mut placed: Vec<Option<QOption>> = Vec::with_capacity(q.options.len());
+        let mut correct = Vec::new();
+        let mut wrong = Vec::new();
+        for opt in q.options.drain(..) {
+            if opt.pinned {
+                placed.push(Some(opt));
+            } else {
+                placed.push(None);
+                if opt.correct {
+                    correct.push(opt);
+                } else {
+                    wrong.push(opt);
+                }
+            }
+        }
+        rng.shuffle(&mut correct);
+        rng.shuffle(&mut wrong);
+        for (slot, opt) in slots.into_iter().zip(correct.into_iter().chain(wrong)) {
+            placed[slot] = Some(opt);
+        }
+        q.options = placed.into_iter().flatten().collect();
+        count_correct(&mut report.after, q);
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process_quiz_str;
+
+    #[test]
+    fn test_annotations_in_stem_and_heading() {
+        let source = "## Trees {reorder}\n\nA tree has 5 nodes. {reorder}\n\nHow many edges?\n\n- [x] 4\n- [ ] 5\n\nHow many roots?\n\n- [x] 1\n- [ ] 2\n";
+        let quiz = process_quiz_str(source, "ex", None).unwrap();
+        for q in quiz.questions() {
+            assert!(q.reorderable);
+            assert_eq!("## Trees\n\nA tree has 5 nodes.", q.stem_markdown);
+            assert!(!q.stem.contains("{reorder}"));
+        }
+    }
+
+    #[test]
+    fn test_balance() {
+        let q = "## Pick the fruit {reorder}\n\n1. [x] apple\n2. [ ] carrot\n3. [ ] potato\n4. [ ] None of the above {pin}\n\n";
+        let fixed = "## Fixed\n\n1. [x] first\n2. [ ] second\n";
+        let source = format!("{}{}{}{}{}{}", q, q, q, q, q, q) + fixed;
+        let mut quiz = process_quiz_str(&source, "ex", None).unwrap();
+        let first = &quiz.sections[0].questions[0];
+        assert!(first.reorderable);
+        assert_eq!("<h2>Pick the fruit</h2>", first.prompt);
+        assert!(first.options[3].pinned);
+        assert_eq!("None of the above", first.options[3].content);
+        assert_eq!("## Pick the fruit", first.prompt_markdown);
+        assert_eq!("None of the above", first.options[3].content_markdown);
+
+        let mut again = quiz.clone();
+        let report = balance(&mut quiz, 7);
+        assert_eq!(6, report.questions);
+        assert_eq!(vec![6], report.before);
+        assert_eq!(vec![2, 2, 2], report.after);
+        for q in quiz.questions().take(6) {
+            assert_eq!("None of the above", q.options[3].content);
+        }
+        let fixed = quiz.questions().last().unwrap();
+        assert_eq!("first", fixed.options[0].content);
+
+        balance(&mut again, 7);
+        let order = |quiz: &Quiz| {
+            quiz.questions()
+                .flat_map(|q| q.options.iter().map(|opt| opt.content.clone()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(order(&quiz), order(&again));
+    }
+}
@@ -0,0 +1,71 @@
+use crate::media::fnv1a;
+use layout::backends::svg::SVGWriter;
+use layout::gv::{DotParser, GraphBuilder};
+
+/// Lay out a Graphviz ``dot`` graph and draw it as an SVG document.
+pub(crate) fn dot_to_svg(source: &str) -> Result<String, String> {
+    let mut parser = DotParser::new(source);
+    let graph = parser.process()?;
+    let mut builder = GraphBuilder::new();
+    builder.visit_graph(&graph);
+    let mut visual = builder.get();
+    // The layout engine panics on empty graphs.
+    if visual.num_nodes() == 0 {
+        return Err("graph has no nodes".to_owned());
+    }
+    let mut svg = SVGWriter::new();
+    visual.do_it(false, false, false, &mut svg);
+    Ok(svg.finalize())
+}
+
+/// Give every ``id`` in ``svg`` (and every reference to one) a ``prefix``, so two diagrams on one page don't share
+/// arrowheads and paths.
+fn prefix_ids(svg: &str, prefix: &str) -> String {
+    svg.replace("id=\"", &format!("id=\"{}", prefix))
+        .replace("url(#", &format!("url(#{}", prefix))
+        .replace("href=\"#", &format!("href=\"#{}", prefix))
+}
+
+/// Render a ``dot`` code block as an inline ``<svg>``.
+pub(crate) fn render_dot(source: &str) -> Result<String, String> {
+    let svg = dot_to_svg(source)?;
+    let svg = match svg.find("<svg") {
+        Some(start) => &svg[start..],
+        None => &svg[..],
+    };
+    let prefix = format!("qd{:08x}-", fnv1a(source.as_bytes()));
+    Ok(format!(
+        "<div class=\"quizdown-diagram\">{}</div>\n",
+        prefix_ids(svg, &prefix)
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dot_to_svg() {
+        let svg = dot_to_svg("digraph { root -> left; root -> right; }").unwrap();
+        assert!(svg.contains("<svg"));
+        assert!(svg.contains(">root<"));
+        assert!(svg.contains(">right<"));
+
+        assert!(dot_to_svg("digraph { root -> ; }").is_err());
+    }
+
+    #[test]
+    fn test_render_dot_inline() {
+        let html = render_dot("digraph { a -> b; }").unwrap();
+        assert!(html.starts_with("<div class=\"quizdown-diagram\"><svg"));
+        assert!(!html.contains("<?xml"));
+        let other = render_dot("digraph { c -> d; }").unwrap();
+        let first_id = |html: &str| {
+            let start = html.find("id=\"").unwrap() + 4;
+            html[start..start + html[start..].find('"').unwrap()].to_owned()
+        };
+        assert_ne!(first_id(&html), first_id(&other));
+        assert!(!html.contains("url(#endarrow)"));
+        assert!(!html.contains("href=\"#arrow0\""));
+    }
+}
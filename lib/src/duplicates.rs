@@ -0,0 +1,198 @@
+use crate::{Position, Question};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+/// One question of a ``Duplicate``.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Location {
+    /// Into the slice given to ``find_duplicates``.
+    pub index: usize,
+    pub file: String,
+    pub position: Position,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}",
+            self.file, self.position.line, self.position.column
+        )
+    }
+}
+
+/// Two questions that ask (nearly) the same thing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Duplicate {
+    pub first: Location,
+    pub second: Location,
+    /// The same ``fingerprint``: identical but for case, punctuation, spacing and option order, with the same
+    /// options correct.
+    pub exact: bool,
+    /// From 0 to 1: how many word pairs the two questions share, out of all the word pairs in either.
+    pub similarity: f64,
+}
+
+impl fmt::Display for Duplicate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.exact {
+            write!(f, "{} and {}: identical", self.first, self.second)
+        } else {
+            write!(
+                f,
+                "{} and {}: {:.0}% similar",
+                self.first,
+                self.second,
+                self.similarity * 100.0
+            )
+        }
+    }
+}
+
+fn words(markdown: &str) -> impl Iterator<Item = String> + '_ {
+    markdown
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase())
+}
+
+/// The words of a question's stem, prompt and options, lower-cased and without punctuation.
+/// Options are sorted, since exports shuffle them anyway, and each starts with ``+`` if it is correct or ``-`` if not;
+/// the same question with a different answer key is not a copy.
+pub fn fingerprint(q: &Question) -> String {
+    let mut options: Vec<String> = q
+        .options
+        .iter()
+        .map(|opt| {
+            let mark = if opt.correct { "+" } else { "-" };
+            std::iter::once(mark.to_owned())
+                .chain(words(&opt.content_markdown))
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect();
+    options.sort();
+    let mut text: Vec<String> = words(&q.stem_markdown)
+        .chain(words(&q.prompt_markdown))
+        .collect();
+    text.extend(options);
+    text.join(" ")
+}
+
+/// Hashes of each pair of neighboring words (or of the one word, if that's all there is).
+fn shingles(fingerprint: &str) -> HashSet<u64> {
+    let words: Vec<&str> = fingerprint.split(' ').collect();
+    let hash = |pair: &[&str]| {
+        let mut hasher = DefaultHasher::new();
+        pair.hash(&mut hasher);
+        hasher.finish()
+    };
+    if words.len() < 2 {
+        return words.chunks(1).map(hash).collect();
+    }
+    words.windows(2).map(hash).collect()
+}
+
+/// Every pair of ``questions``, (file name, question) pairs, at least ``threshold`` similar (see
+/// ``Duplicate::similarity``), most similar first.
+/// Compares every pair, which is quick enough for banks of a few thousand questions.
+pub fn find_duplicates(questions: &[(String, Question)], threshold: f64) -> Vec<Duplicate> {
+    let location = |index: usize| {
+        let (file, q) = &questions[index];
+        Location {
+            index,
+            file: file.clone(),
+            position: q.position.unwrap_or_default(),
+        }
+    };
+    let fingerprints: Vec<String> = questions.iter().map(|(_, q)| fingerprint(q)).collect();
+    let shingles: Vec<HashSet<u64>> = fingerprints.iter().map(|f| shingles(f)).collect();
+    let mut found = Vec::new();
+    for first in 0..questions.len() {
+        for second in (first + 1)..questions.len() {
+            let (a, b) = (&shingles[first], &shingles[second]);
+            let shared = a.intersection(b).count();
+            let similarity = shared as f64 / (a.len() + b.len() - shared).max(1) as f64;
+            let exact = fingerprints[first] == fingerprints[second];
+            if exact || similarity >= threshold {
+                found.push(Duplicate {
+                    first: location(first),
+                    second: location(second),
+                    exact,
+                    similarity: if exact { 1.0 } else { similarity },
+                });
+            }
+        }
+    }
+    found.sort_by(|x, y| {
+        y.similarity
+            .partial_cmp(&x.similarity)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process_questions_str;
+
+    #[test]
+    fn test_find_duplicates() {
+        let qs: Vec<(String, Question)> = process_questions_str(
+            r#"
+## What does ``len([1, 2, 3])`` return?
+
+- [x] 3
+- [ ] 2
+
+## What does len([1,2,3]) return
+
+- [ ] 2
+- [x] 3
+
+## What does ``len([1, 2, 3, 4])`` return?
+
+- [x] 4
+- [ ] 2
+
+## Which of these is a mutable sequence?
+
+- [x] list
+- [ ] tuple
+"#,
+            None,
+        )
+        .unwrap()
+        .into_iter()
+        .map(|q| ("bank.md".to_owned(), q))
+        .collect();
+        let found = find_duplicates(&qs, 0.5);
+        assert_eq!(3, found.len());
+        assert_eq!(
+            (0, 1, true),
+            (found[0].first.index, found[0].second.index, found[0].exact)
+        );
+        assert_eq!(1.0, found[0].similarity);
+        assert_eq!(
+            "bank.md:2:1 and bank.md:7:1: identical",
+            found[0].to_string()
+        );
+        assert!(!found[1].exact);
+        assert!(found[1].similarity < 1.0);
+        assert!(found.iter().all(|d| d.second.index != 3));
+
+        // Same words, different answer key:
+        let mut rekeyed = qs[1].clone();
+        rekeyed.0 = "other.md".to_owned();
+        for opt in rekeyed.1.options.iter_mut() {
+            opt.correct = !opt.correct;
+        }
+        let found = find_duplicates(&[qs[0].clone(), rekeyed], 0.5);
+        assert_eq!(1, found.len());
+        assert!(!found[0].exact);
+        assert_eq!("other.md", found[0].second.file);
+    }
+}
@@ -1,48 +1,295 @@
-use crate::{Error, Question};
+use crate::mathml::{escape, html_math_to_mathml};
+use crate::media::{replace_srcs, MediaFile};
+use crate::{Error, Question, Quiz, Section};
 use std::fmt::Write;
 
+/// ``<title>`` plus the shared styles; callers add scripts before closing the ``<head>``.
+fn open_page(output: &mut String, quiz: &Quiz) {
+    output.push_str("<html><head>");
+    write!(output, "<title>{}</title>", escape(quiz.title())).unwrap();
+    output.push_str(include_str!("head.html"));
+}
+
+fn push_section_title(output: &mut String, section: &Section) {
+    if let Some(title) = section.title.as_ref() {
+        write!(
+            output,
+            "<h1 class='quizdown-section'>{}</h1>",
+            escape(title)
+        )
+        .unwrap();
+    }
+    if let Some(description) = section.description.as_ref() {
+        write!(
+            output,
+            "<div class='quizdown-description'>{}</div>",
+            description
+        )
+        .unwrap();
+    }
+}
+
+fn push_description(output: &mut String, quiz: &Quiz) {
+    if let Some(description) = quiz.description.as_ref() {
+        write!(
+            output,
+            "<div class='quizdown-description'>{}</div>",
+            description
+        )
+        .unwrap();
+    }
+}
+
+/// The shared ``stem`` of a multi-part question, once, before its first part.
+fn push_stem(output: &mut String, q: &Question) {
+    if q.part.map(|part| part.index == 0).unwrap_or(false) {
+        write!(output, "<div class='quizdown-stem'>{}</div>", q.stem).unwrap();
+    }
+}
+
+fn push_explanation(output: &mut String, q: &Question, hidden: bool) {
+    if !q.explanation.is_empty() {
+        write!(
+            output,
+            "<div class='quizdown-explanation'{}>{}</div>",
+            if hidden { " hidden" } else { "" },
+            q.explanation
+        )
+        .unwrap();
+    }
+}
+
+/// ``<style>`` for a syntax-highlighting stylesheet, if there is one.
+fn push_stylesheet(output: &mut String, stylesheet: Option<&str>) {
+    if let Some(css) = stylesheet {
+        output.push_str("<style>");
+        output.push_str(css);
+        output.push_str("</style>");
+    }
+}
+
 pub fn render_html_preview(
-    name: &str,
-    questions: &[Question],
+    quiz: &Quiz,
     full_page: bool,
+    stylesheet: Option<&str>,
 ) -> Result<String, Error> {
     let mut output = String::new();
     if full_page {
-        output.push_str("<html><head>");
-        output.push_str(include_str!("head.html"));
+        open_page(&mut output, quiz);
+        output.push_str(include_str!("mathjax.html"));
+        push_stylesheet(&mut output, stylesheet);
         output.push_str("</head><body>");
     }
     writeln!(
         &mut output,
         "<i class='quizdown-loaded'>Loaded from {:?}</i>",
-        name
+        quiz.name
     )?;
-    for q in questions.iter() {
-        output.push_str("<div class='quizdown-question'>");
-        writeln!(
-            &mut output,
-            "<div class='quizdown-prompt'>{}</div>",
-            q.prompt
-        )?;
-        output.push_str(if q.ordered { "<ol>" } else { "<ul>" });
-        for (i, opt) in q.options.iter().enumerate() {
+    push_description(&mut output, quiz);
+    for section in quiz.sections.iter() {
+        push_section_title(&mut output, section);
+        for q in section.questions.iter() {
+            output.push_str("<div class='quizdown-question'>");
+            // Authors see titles; students (in practice pages) don't.
+            if !q.title.is_empty() {
+                write!(
+                    &mut output,
+                    "<i class='quizdown-title'>{}</i>",
+                    escape(&q.title)
+                )?;
+            }
+            push_stem(&mut output, q);
             writeln!(
                 &mut output,
-                "<li class='quizdown-option'>
+                "<div class='quizdown-prompt'>{}</div>",
+                q.prompt
+            )?;
+            output.push_str(if q.ordered { "<ol>" } else { "<ul>" });
+            for (i, opt) in q.options.iter().enumerate() {
+                writeln!(
+                    &mut output,
+                    "<li class='quizdown-option'>
                     <input id='opt{}' type='checkbox' {} />
                     <label class='quizdown-label' for='opt{}'>{}</label>
                 </li>",
-                i,
-                if opt.correct { "checked" } else { "" },
-                i,
-                opt.content
-            )?;
+                    i,
+                    if opt.correct { "checked" } else { "" },
+                    i,
+                    opt.content
+                )?;
+            }
+            output.push_str(if q.ordered { "</ol>" } else { "</ul>" });
+            push_explanation(&mut output, q, false);
+            output.push_str("</div>");
         }
-        output.push_str(if q.ordered { "</ol>" } else { "</ul>" });
-        output.push_str("</div>");
     }
     if full_page {
         output.push_str("</body></html>");
     }
     Ok(output)
 }
+
+/// A copy of ``quiz`` that needs no network: math pre-rendered to MathML and local images as data URIs.
+fn inline_for_offline(quiz: &Quiz) -> Quiz {
+    let inline = |html: &str, media: &[MediaFile]| {
+        html_math_to_mathml(&replace_srcs(html, media, MediaFile::data_uri))
+    };
+    let mut inlined = quiz.clone();
+    inlined.description = quiz.description.as_deref().map(|d| inline(d, &[]));
+    for section in inlined.sections.iter_mut() {
+        section.description = section.description.as_deref().map(|d| inline(d, &[]));
+    }
+    for q in inlined.questions_mut() {
+        q.stem = inline(&q.stem, &q.media);
+        q.prompt = inline(&q.prompt, &q.media);
+        q.explanation = inline(&q.explanation, &q.media);
+        for opt in q.options.iter_mut() {
+            opt.content = inline(&opt.content, &opt.media);
+        }
+    }
+    inlined
+}
+
+/// Render a full preview page that works offline: no CDN scripts, math pre-rendered to MathML and local images inlined.
+pub fn render_html_standalone(quiz: &Quiz, stylesheet: Option<&str>) -> Result<String, Error> {
+    let mut output = String::new();
+    open_page(&mut output, quiz);
+    push_stylesheet(&mut output, stylesheet);
+    output.push_str("</head><body>");
+    output.push_str(&render_html_preview(
+        &inline_for_offline(quiz),
+        false,
+        None,
+    )?);
+    output.push_str("</body></html>");
+    Ok(output)
+}
+
+/// Render a full, self-grading practice page: answers are hidden until a student presses "Check".
+///
+/// Questions with exactly one correct option use radio buttons and are graded on that one choice; the rest use
+/// checkboxes and are graded all-or-nothing. With ``Quiz::hide_answer_count``, every question uses checkboxes.
+/// Like ``render_html_standalone``, the page works offline.
+pub fn render_html_interactive(quiz: &Quiz, stylesheet: Option<&str>) -> Result<String, Error> {
+    let quiz = &inline_for_offline(quiz);
+    let mut output = String::new();
+    open_page(&mut output, quiz);
+    output.push_str(include_str!("interactive.html"));
+    push_stylesheet(&mut output, stylesheet);
+    output.push_str("</head><body>");
+    writeln!(
+        &mut output,
+        "<i class='quizdown-loaded'>Loaded from {:?}</i>",
+        quiz.name
+    )?;
+    push_description(&mut output, quiz);
+    let mut q_index = 0;
+    for section in quiz.sections.iter() {
+        push_section_title(&mut output, section);
+        for q in section.questions.iter() {
+            let single =
+                !quiz.hide_answer_count && q.options.iter().filter(|opt| opt.correct).count() == 1;
+            output.push_str("<form class='quizdown-question' onsubmit='return false;'>");
+            push_stem(&mut output, q);
+            writeln!(
+                &mut output,
+                "<div class='quizdown-prompt'>{}</div>",
+                q.prompt
+            )?;
+            output.push_str(if q.ordered { "<ol>" } else { "<ul>" });
+            for (i, opt) in q.options.iter().enumerate() {
+                writeln!(
+                    &mut output,
+                    "<li class='quizdown-option'>
+                    <input id='q{q}opt{i}' name='q{q}' type='{kind}' data-correct='{correct}' />
+                    <label class='quizdown-label' for='q{q}opt{i}'>{content}</label>
+                </li>",
+                    q = q_index,
+                    i = i,
+                    kind = if single { "radio" } else { "checkbox" },
+                    correct = opt.correct,
+                    content = opt.content
+                )?;
+            }
+            output.push_str(if q.ordered { "</ol>" } else { "</ul>" });
+            output.push_str("<button type='button' class='quizdown-check'>Check</button>");
+            output.push_str("<span class='quizdown-feedback'></span>");
+            push_explanation(&mut output, q, true);
+            output.push_str("</form>");
+            q_index += 1;
+        }
+    }
+    output.push_str("<div class='quizdown-score'></div>");
+    output.push_str("</body></html>");
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process_quiz_str;
+
+    #[test]
+    fn test_interactive_hides_answers() {
+        let quiz = process_quiz_str(
+            r#"
+## Single?
+
+- [ ] No
+- [x] Yes
+
+## Multi?
+
+- [x] A
+- [x] B
+- [ ] C
+
+Both A and B.
+        "#,
+            "ex",
+            None,
+        )
+        .unwrap();
+        let html = render_html_interactive(&quiz, None).unwrap();
+        assert!(html
+            .lines()
+            .filter(|line| line.contains("<input"))
+            .all(|line| !line.contains("checked")));
+        assert_eq!(2, html.matches("type='radio'").count());
+        assert_eq!(3, html.matches("type='checkbox'").count());
+        assert!(!html.contains("cdnjs"));
+        assert_eq!(3, html.matches("data-correct='true'").count());
+        assert!(
+            html.contains("<input id='q1opt2' name='q1' type='checkbox' data-correct='false' />")
+        );
+        assert!(html.contains("<div class='quizdown-explanation' hidden><p>Both A and B.</p>"));
+
+        let mut hidden = quiz.clone();
+        hidden.hide_answer_count = true;
+        let html = render_html_interactive(&hidden, None).unwrap();
+        assert_eq!(0, html.matches("type='radio'").count());
+        assert_eq!(5, html.matches("type='checkbox'").count());
+    }
+
+    #[test]
+    fn test_standalone_is_offline() {
+        let quiz = process_quiz_str(
+            r#"
+## Which is \\(O(n^2)\\)?
+
+- [x] ``bubble_sort``
+- [ ] ``merge_sort``
+        "#,
+            "ex",
+            None,
+        )
+        .unwrap();
+        let html = render_html_standalone(&quiz, None).unwrap();
+        assert!(!html.contains("cdnjs"));
+        assert!(!html.contains("<script"));
+        assert!(html.contains(
+            "<h2>Which is <math xmlns=\"http://www.w3.org/1998/Math/MathML\" display=\"inline\">"
+        ));
+        assert!(html.contains("bubble_sort"));
+    }
+}
@@ -1,17 +1,35 @@
-use parsing::QParser;
+use parsing::{Chunk, QParser};
 use pulldown_cmark::{Options, Parser};
+use render::SyntaxHighlighter;
+use schemars::JsonSchema;
+use std::collections::HashMap;
 use std::fs;
 use std::io;
-use syntect::highlighting::ThemeSet;
+use std::path::{Path, PathBuf};
 
 #[macro_use]
 extern crate serde_derive;
+#[macro_use]
+extern crate lazy_static;
 
+pub mod balance;
+mod diagram;
+pub mod duplicates;
 pub mod html;
+pub mod lint;
+pub mod manifest;
+mod math;
+pub mod mathml;
+mod media;
 pub mod moodlexml;
 mod parsing;
 mod render;
-pub use render::SyntaxHighlightingOptions;
+pub mod stats;
+#[cfg(test)]
+mod testing;
+pub use media::MediaFile;
+pub use parsing::{Preamble, SplitOptions};
+pub use render::{CodeBlockTransform, HighlightStyle, SyntaxHighlightingOptions};
 
 #[derive(Serialize, Deserialize, Ord, PartialOrd, Eq, PartialEq, Clone, Debug, Copy)]
 pub enum OutputFormat {
@@ -19,30 +37,87 @@ pub enum OutputFormat {
     HtmlFull,
     /// Render just the HTML for the questions themselves.
     HtmlSnippet,
+    /// Render a self-grading HTML practice page with the answers hidden.
+    HtmlInteractive,
+    /// Render HTML like ``HtmlFull``, but with no CDN scripts: math becomes MathML and local images become data URIs.
+    HtmlStandalone,
     /// MoodleXML import format.
     MoodleXml,
     /// JSON output format (also for FFI to Python)
     JSON,
 }
 
-#[derive(Serialize)]
-struct OutputQuestions<'a> {
-    name: &'a str,
-    questions: &'a [Question],
-}
-
 impl OutputFormat {
-    pub fn render(&self, name: &str, questions: &[Question]) -> Result<String, Error> {
+    /// Look up a format by the short name used on the command line and in manifests, e.g., "html" or "moodle".
+    pub fn from_short_name(name: &str) -> Option<OutputFormat> {
+        Some(match name {
+            "html" => OutputFormat::HtmlFull,
+            "snippet" => OutputFormat::HtmlSnippet,
+            "interactive" => OutputFormat::HtmlInteractive,
+            "standalone" => OutputFormat::HtmlStandalone,
+            "moodle" => OutputFormat::MoodleXml,
+            "json" => OutputFormat::JSON,
+            _ => return None,
+        })
+    }
+    /// Guess a format from an output file name, e.g., "quiz.moodle".
+    pub fn from_file_name(path: &str) -> Option<OutputFormat> {
+        if path.ends_with(".html") {
+            Some(OutputFormat::HtmlFull)
+        } else if path.ends_with(".moodle") {
+            Some(OutputFormat::MoodleXml)
+        } else if path.ends_with(".json") {
+            Some(OutputFormat::JSON)
+        } else {
+            None
+        }
+    }
+    /// The file extension we use when choosing output names for this format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::HtmlFull => "html",
+            OutputFormat::HtmlSnippet => "snippet.html",
+            OutputFormat::HtmlInteractive => "quiz.html",
+            OutputFormat::HtmlStandalone => "standalone.html",
+            OutputFormat::MoodleXml => "moodle",
+            OutputFormat::JSON => "json",
+        }
+    }
+    /// Whole pages (with a ``<head>``) rather than fragments or data.
+    pub fn is_full_page(&self) -> bool {
+        matches!(
+            self,
+            OutputFormat::HtmlFull | OutputFormat::HtmlInteractive | OutputFormat::HtmlStandalone
+        )
+    }
+    pub fn render(&self, quiz: &Quiz) -> Result<String, Error> {
+        self.render_with_stylesheet(quiz, None)
+    }
+    /// Like ``render``, but full pages also get ``stylesheet`` in their ``<head>``, e.g., from ``SyntaxHighlightingOptions::stylesheet``.
+    pub fn render_with_stylesheet(
+        &self,
+        quiz: &Quiz,
+        stylesheet: Option<&str>,
+    ) -> Result<String, Error> {
         Ok(match self {
             OutputFormat::HtmlFull | OutputFormat::HtmlSnippet => {
-                html::render_html_preview(name, questions, self == &OutputFormat::HtmlFull)?
+                html::render_html_preview(quiz, self == &OutputFormat::HtmlFull, stylesheet)?
             }
-            OutputFormat::MoodleXml => moodlexml::to_moodle_xml(questions, name)?,
-            OutputFormat::JSON => serde_json::to_string(&OutputQuestions { name, questions })?,
+            OutputFormat::HtmlInteractive => html::render_html_interactive(quiz, stylesheet)?,
+            OutputFormat::HtmlStandalone => html::render_html_standalone(quiz, stylesheet)?,
+            OutputFormat::MoodleXml => moodlexml::to_moodle_xml(quiz)?,
+            OutputFormat::JSON => quiz.to_json()?,
         })
     }
 }
 
+/// Where the highlighting stylesheet for a non-page output goes, e.g., ``quiz.moodle.css`` next to ``quiz.moodle``.
+pub fn stylesheet_path(output: &Path) -> PathBuf {
+    let mut name = output.as_os_str().to_owned();
+    name.push(".css");
+    PathBuf::from(name)
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("Format-Err {0:?}")]
@@ -55,79 +130,433 @@ pub enum Error {
     Unexpected(String),
     #[error("Non-uint points {0}")]
     PointsParseErr(#[from] std::num::ParseIntError),
-    #[error("Nested list options are not supported.")]
-    NestedTaskList,
+    #[error("Found a checkbox in a nested list at line {line}, column {column}; only the outermost list can hold options.")]
+    NestedTaskList { line: usize, column: usize },
     #[error("Internal: TaskList event without List event?")]
     TaskListWithoutList,
-    #[error("Found multiple lists with options; not supported.")]
-    TooManyTaskLists,
     #[error("Found no options in question.")]
     NoOptionsFound,
     #[error("Moodle requires correct answers for every question!")]
     MoodleNoCorrectAnswer,
-    #[error("Content ignored after options!")]
-    ContentIgnored,
     #[error("Internal Assertion Error")]
     Internal,
     #[error("Missing Syntax theme: '{0}'")]
     MissingSyntaxTheme(String),
     #[error("Missing Syntax for language: '{0}'")]
     MissingSyntaxLang(String),
+    #[error("Syntax/Theme Loading Error: {0}")]
+    SyntaxLoading(#[from] syntect::LoadingError),
     #[error("JSON Error: {0}")]
     JSONError(#[from] serde_json::Error),
+    #[error("Manifest Error: {0}")]
+    ManifestError(#[from] toml::de::Error),
+    #[error("Unknown output format: '{0}'")]
+    UnknownFormat(String),
+    #[error("Diagram error at line {line}, column {column}: {message}")]
+    DiagramError {
+        message: String,
+        line: usize,
+        column: usize,
+    },
+    #[error("Lint found {0} error(s).")]
+    LintFailed(usize),
+    #[error("Unsupported quiz JSON version {0}; expected {}", SCHEMA_VERSION)]
+    SchemaVersion(u64),
+    #[error("Expected {expected} at line {line}, column {column}, but found {found}.")]
+    UnexpectedMarkdown {
+        expected: String,
+        found: String,
+        line: usize,
+        column: usize,
+    },
+    #[error("Missing image '{path}' at line {line}, column {column}")]
+    MissingMedia {
+        path: String,
+        line: usize,
+        column: usize,
+    },
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
 pub struct Config {
     pub insert_none_of_the_above: bool,
+    /// Use each question's heading as its ``title`` (e.g., the Moodle question name) instead of showing it to
+    /// students as the start of the prompt.
+    pub heading_as_title: bool,
     pub syntax: SyntaxHighlightingOptions,
+    pub split: SplitOptions,
 }
 
-#[derive(Serialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 pub struct QOption {
     pub correct: bool,
+    /// Kept in place by ``balance::balance``, e.g., "All of the above": the option ended with ``{pin}``.
+    #[serde(default)]
+    pub pinned: bool,
+    /// HTML.
     pub content: String,
+    /// The markdown ``content`` was rendered from, after the ``[x]`` and without the list's indentation.
+    #[serde(default)]
+    pub content_markdown: String,
+    /// Local images referenced by ``content``.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub media: Vec<MediaFile>,
+    /// Where the option's ``[x]`` is in its markdown file; not part of the JSON.
+    #[serde(skip)]
+    pub position: Option<Position>,
 }
 
-#[derive(Serialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 pub struct Question {
+    /// A name for the question, from its heading, when ``Config::heading_as_title`` is set.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub title: String,
+    /// HTML, including the heading the question started with (unless it is one ``part`` of several, or the heading
+    /// is its ``title``).
     pub prompt: String,
+    /// The markdown ``prompt`` was rendered from, heading included.
+    #[serde(default)]
+    pub prompt_markdown: String,
+    /// HTML shared by every part of a multi-part question: the heading and anything before the first prompt.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub stem: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub stem_markdown: String,
+    /// Which part this is, for a heading followed by several lists of options.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub part: Option<Part>,
     pub options: Vec<QOption>,
+    /// Whether options were a numbered list.
+    #[serde(default)]
     pub ordered: bool,
+    /// Whether ``balance::balance`` may reorder numbered options: the heading or prompt ended with ``{reorder}``.
+    #[serde(default)]
+    pub reorderable: bool,
+    /// From ``{tag=NAME}`` annotations on the heading or prompt, in the order written.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// From a ``{difficulty=LEVEL}`` annotation; the scale (``easy``/``hard``, ``1``-``5``, ...) is up to the author.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub difficulty: Option<String>,
+    /// HTML to show once the question is answered: anything written after the options.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub explanation: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub explanation_markdown: String,
+    /// Local images referenced by ``stem``, ``prompt`` or ``explanation``.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub media: Vec<MediaFile>,
+    /// Where the question starts in its markdown file; not part of the JSON.
+    #[serde(skip)]
+    pub position: Option<Position>,
+}
+
+/// A line and column in a markdown file, both counting from one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Where a question sits in a multi-part question; ``index`` counts from zero.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Part {
+    pub index: usize,
+    pub count: usize,
+}
+
+impl Question {
+    /// ``stem`` followed by ``prompt``: everything to show for this question on its own.
+    pub fn full_prompt(&self) -> String {
+        format!("{}{}", self.stem, self.prompt)
+    }
+}
+
+/// The ``version`` written into JSON output; bumped whenever that format changes incompatibly.
+pub const SCHEMA_VERSION: u64 = 1;
+
+/// What ``Quiz::to_json`` writes: the quiz plus its ``version``.
+#[derive(Serialize, JsonSchema)]
+#[schemars(rename = "Quiz")]
+struct VersionedQuiz<'a> {
+    /// ``SCHEMA_VERSION``; may be left out of input.
+    #[schemars(default = "schema_version")]
+    version: u64,
+    #[serde(flatten)]
+    quiz: &'a Quiz,
+}
+
+/// A whole quiz: its questions, grouped into sections, plus the quiz-level settings exporters need.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default)]
+pub struct Quiz {
+    /// Identifies the quiz, e.g., "cs101/lists"; by default, the input file name.
+    pub name: String,
+    /// Shown to students; ``name`` if missing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// The Moodle question category; ``name`` if missing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+    /// The markdown file this quiz was read from, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// For exports that can enforce one, e.g., QTI.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_limit_minutes: Option<u32>,
+    /// HTML introducing the quiz; see ``Preamble::Description``.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Use checkboxes for every question in practice pages, even those with one correct option, so the inputs don't
+    /// give away how many answers there are.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub hide_answer_count: bool,
+    pub sections: Vec<Section>,
+}
+
+/// A run of questions; titled sections become sub-categories in Moodle and headings in HTML.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default)]
+pub struct Section {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// HTML introducing the section: any markdown between its heading and its first question.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub questions: Vec<Question>,
+}
+
+impl Quiz {
+    /// A quiz with one untitled section.
+    pub fn new(name: &str, questions: Vec<Question>) -> Quiz {
+        Quiz {
+            name: name.to_owned(),
+            sections: vec![Section {
+                title: None,
+                description: None,
+                questions,
+            }],
+            ..Quiz::default()
+        }
+    }
+    pub fn title(&self) -> &str {
+        self.title.as_deref().unwrap_or(&self.name)
+    }
+    pub fn category(&self) -> &str {
+        self.category.as_deref().unwrap_or(&self.name)
+    }
+    /// Every question, in order, ignoring sections.
+    pub fn questions(&self) -> impl Iterator<Item = &Question> {
+        self.sections.iter().flat_map(|s| s.questions.iter())
+    }
+    pub fn questions_mut(&mut self) -> impl Iterator<Item = &mut Question> {
+        self.sections
+            .iter_mut()
+            .flat_map(|s| s.questions.iter_mut())
+    }
+    pub fn into_questions(self) -> Vec<Question> {
+        self.sections
+            .into_iter()
+            .flat_map(|s| s.questions)
+            .collect()
+    }
+    pub fn to_json(&self) -> Result<String, Error> {
+        Ok(serde_json::to_string(&VersionedQuiz {
+            version: SCHEMA_VERSION,
+            quiz: self,
+        })?)
+    }
+    /// Read a quiz written by ``to_json`` or generated by another program; ``prompt`` and ``content`` are HTML.
+    /// A missing ``version`` means the current one, and a bare list of questions (the JSON of older releases)
+    /// becomes a quiz with an empty name.
+    pub fn from_json(json: &str) -> Result<Quiz, Error> {
+        let value: serde_json::Value = serde_json::from_str(json)?;
+        if value.is_array() {
+            return Ok(Quiz::new("", serde_json::from_value(value)?));
+        }
+        // Only a missing version means "current"; ``"2"`` or ``2.0`` is a mistake, not version 1.
+        let version = match value.get("version") {
+            None => SCHEMA_VERSION,
+            Some(v) => v.as_u64().ok_or_else(|| {
+                Error::Unexpected(format!(
+                    "quiz JSON version {}; expected an unsigned integer like {}",
+                    v, SCHEMA_VERSION
+                ))
+            })?,
+        };
+        if version != SCHEMA_VERSION {
+            return Err(Error::SchemaVersion(version));
+        }
+        Ok(serde_json::from_value(value)?)
+    }
+}
+
+fn schema_version() -> u64 {
+    SCHEMA_VERSION
+}
+
+/// A JSON Schema (draft-07) describing ``Quiz::to_json`` output, for tools that read or write quizzes.
+pub fn json_schema() -> String {
+    let schema = schemars::schema_for!(VersionedQuiz);
+    serde_json::to_string_pretty(&schema).expect("schemas are always serializable")
 }
 
 pub fn list_themes() -> Vec<String> {
-    let ts = ThemeSet::load_defaults();
-    let mut themes = ts.themes.keys().cloned().collect::<Vec<String>>();
+    let mut themes = render::DEFAULT_THEMES
+        .themes
+        .keys()
+        .cloned()
+        .collect::<Vec<String>>();
     themes.sort_unstable();
     themes
 }
 
+/// Turns markdown into questions with one ``Config``; keep one around to process many inputs (e.g., on every
+/// keystroke in an editor) without re-loading syntaxes and themes each time.
+pub struct Compiler {
+    config: Config,
+    highlighter: SyntaxHighlighter,
+    code_block_transforms: HashMap<String, CodeBlockTransform>,
+}
+
+impl Compiler {
+    pub fn new(config: Config) -> Result<Compiler, Error> {
+        let highlighter = config.syntax.create()?;
+        Ok(Compiler {
+            config,
+            highlighter,
+            code_block_transforms: render::default_code_block_transforms(),
+        })
+    }
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+    /// Render fenced code blocks in ``lang`` with ``transform`` instead of highlighting them; ``dot`` and
+    /// ``graphviz`` start out drawn as diagrams. An ``Err`` from ``transform`` becomes ``Error::DiagramError`` at
+    /// the block's position.
+    pub fn set_code_block_transform(&mut self, lang: &str, transform: CodeBlockTransform) {
+        self.code_block_transforms
+            .insert(lang.to_owned(), transform);
+    }
+    /// Highlight fenced code blocks in ``lang`` as code again, even if they had a transform.
+    pub fn remove_code_block_transform(&mut self, lang: &str) {
+        self.code_block_transforms.remove(lang);
+    }
+    pub fn process_str(&self, content: &str) -> Result<Vec<Question>, Error> {
+        Ok(self.process(content, None)?.into_questions())
+    }
+    pub fn process_file(&self, path: &str) -> Result<Vec<Question>, Error> {
+        let contents = fs::read_to_string(path)?;
+        Ok(self
+            .process(&contents, Path::new(path).parent())?
+            .into_questions())
+    }
+    pub fn quiz_from_str(&self, name: &str, content: &str) -> Result<Quiz, Error> {
+        let mut quiz = self.process(content, None)?;
+        quiz.name = name.to_owned();
+        Ok(quiz)
+    }
+    /// Reads markdown, or ``Quiz::from_json`` if ``path`` ends in ``.json``.
+    /// ``name`` defaults to the name inside the JSON, or else ``path``, as on the command line.
+    pub fn quiz_from_file(&self, path: &str, name: Option<&str>) -> Result<Quiz, Error> {
+        let mut quiz = if path.ends_with(".json") {
+            Quiz::from_json(&fs::read_to_string(path)?)?
+        } else {
+            let contents = fs::read_to_string(path)?;
+            self.process(&contents, Path::new(path).parent())?
+        };
+        if let Some(name) = name {
+            quiz.name = name.to_owned();
+        } else if quiz.name.is_empty() {
+            quiz.name = path.to_owned();
+        }
+        quiz.source = Some(path.to_owned());
+        Ok(quiz)
+    }
+    /// An unnamed quiz, split into sections and questions by ``Config::split``.
+    /// ``base`` is the directory that relative image paths are resolved against, if we know it.
+    pub(crate) fn process(&self, content: &str, base: Option<&Path>) -> Result<Quiz, Error> {
+        let mut quiz = Quiz::new("", Vec::new());
+        let mut md_opt = Options::empty();
+        md_opt.insert(Options::ENABLE_STRIKETHROUGH);
+        md_opt.insert(Options::ENABLE_TABLES);
+        md_opt.insert(Options::ENABLE_TASKLISTS);
+        let (protected, math) = math::protect_math(content, md_opt);
+        let parser = Parser::new_ext(&protected, md_opt);
+        let events = math::restore_math(parser.into_offset_iter(), &math);
+        media::check_media(&protected, &events, base)?;
+        let events = render::transform_code_blocks(
+            content,
+            events,
+            &self.code_block_transforms,
+            &self.config.syntax.default_lang,
+        )?;
+        let mut qp = QParser::new(content, events, self.config.split.clone());
+
+        while let Some(chunk) = qp.parse_next()? {
+            let last = quiz.sections.len() - 1;
+            match chunk {
+                Chunk::Question(chunk) => {
+                    // Offsets into ``protected`` are offsets into ``content``, too.
+                    let questions = chunk.finish(
+                        &self.highlighter,
+                        content,
+                        base,
+                        self.config.heading_as_title,
+                    )?;
+                    quiz.sections[last].questions.extend(questions);
+                }
+                Chunk::Section(title) => {
+                    // A section with no questions yet (e.g., the first) just gets a title.
+                    if quiz.sections[last].questions.is_empty() {
+                        quiz.sections[last].title = Some(title);
+                    } else {
+                        quiz.sections.push(Section {
+                            title: Some(title),
+                            description: None,
+                            questions: Vec::new(),
+                        });
+                    }
+                }
+                Chunk::Intro(events) => {
+                    let mut description = String::new();
+                    self.highlighter.render(&mut description, &events)?;
+                    quiz.sections[last].description = Some(description);
+                }
+                Chunk::Preamble(events) => {
+                    if self.config.split.preamble == Preamble::Description {
+                        let mut description = String::new();
+                        self.highlighter.render(&mut description, &events)?;
+                        quiz.description = Some(description);
+                    }
+                }
+            }
+        }
+
+        Ok(quiz)
+    }
+}
+
 pub fn process_questions_str(
     content: &str,
     config: Option<Config>,
 ) -> Result<Vec<Question>, Error> {
-    let mut output = Vec::new();
-    let config = config.unwrap_or_default();
-    let highlighter = config.syntax.create()?;
-
-    let mut md_opt = Options::empty();
-    md_opt.insert(Options::ENABLE_STRIKETHROUGH);
-    md_opt.insert(Options::ENABLE_TABLES);
-    md_opt.insert(Options::ENABLE_TASKLISTS);
-    let parser = Parser::new_ext(content, md_opt);
-    let mut qp = QParser::new(parser);
+    Compiler::new(config.unwrap_or_default())?.process_str(content)
+}
 
-    while let Some(chunk) = qp.parse_next()? {
-        output.push(chunk.finish(&highlighter)?);
-    }
+pub fn process_questions_file(path: &str, config: Option<Config>) -> Result<Vec<Question>, Error> {
+    Compiler::new(config.unwrap_or_default())?.process_file(path)
+}
 
-    Ok(output)
+pub fn process_quiz_str(content: &str, name: &str, config: Option<Config>) -> Result<Quiz, Error> {
+    Compiler::new(config.unwrap_or_default())?.quiz_from_str(name, content)
 }
 
-pub fn process_questions_file(path: &str, config: Option<Config>) -> Result<Vec<Question>, Error> {
-    let contents = fs::read_to_string(path)?;
-    process_questions_str(&contents, config)
+pub fn process_quiz_file(
+    path: &str,
+    name: Option<&str>,
+    config: Option<Config>,
+) -> Result<Quiz, Error> {
+    Compiler::new(config.unwrap_or_default())?.quiz_from_file(path, name)
 }
 
 #[cfg(test)]
@@ -145,6 +574,232 @@ mod tests {
         assert_eq!(1, qs.len());
     }
 
+    #[test]
+    fn test_math_q() {
+        let math_q = r#"
+## Is $a_1 * b_1 < a_2 * b_2$?
+
+$$
+\sum_{i=1}^n i
+$$
+
+- [ ] $x_*$
+- [x] No
+        "#;
+        let qs = process_questions_str(math_q, None).unwrap();
+        assert_eq!(
+            "<h2>Is \\(a_1 * b_1 &lt; a_2 * b_2\\)?</h2><p>\\[\\sum_{i=1}^n i\\]</p>\n",
+            qs[0].prompt
+        );
+        assert_eq!("\\(x_*\\)", qs[0].options[0].content);
+    }
+
+    #[test]
+    fn test_quiz_json_round_trip() {
+        let mut quiz = process_quiz_str(
+            "## Who let the dogs out?\n\n- [ ] I did it.\n- [x] Who, who, who?\n",
+            "cs101/dogs",
+            None,
+        )
+        .unwrap();
+        quiz.title = Some("Dogs & Cats".to_owned());
+        quiz.time_limit_minutes = Some(10);
+        assert_eq!("cs101/dogs", quiz.category());
+        assert_eq!(1, quiz.questions().count());
+
+        let json = OutputFormat::JSON.render(&quiz).unwrap();
+        assert!(json.starts_with("{\"version\":1,\"name\":\"cs101/dogs\""));
+        let back = Quiz::from_json(&json).unwrap();
+        assert_eq!("Dogs & Cats", back.title());
+        assert_eq!(Some(10), back.time_limit_minutes);
+        assert_eq!(None, back.sections[0].title);
+        let q = back.questions().next().unwrap();
+        assert!(q.options[1].correct);
+        assert_eq!(json, OutputFormat::JSON.render(&back).unwrap());
+    }
+
+    #[test]
+    fn test_json_input() {
+        let generated = r#"{"name": "gen", "sections": [{"questions": [
+            {"prompt": "<p>2 + 2?</p>", "options": [
+                {"correct": false, "content": "3"},
+                {"correct": true, "content": "4"}
+            ]}
+        ]}]}"#;
+        let quiz = Quiz::from_json(generated).unwrap();
+        let xml = OutputFormat::MoodleXml.render(&quiz).unwrap();
+        assert!(xml.contains("<name><text>gen/0</text></name>"));
+
+        let legacy = r#"[{"prompt": "<p>Q</p>", "options": [], "ordered": true}]"#;
+        let quiz = Quiz::from_json(legacy).unwrap();
+        assert!(quiz.questions().next().unwrap().ordered);
+
+        match Quiz::from_json(r#"{"version": 2, "name": "future", "sections": []}"#) {
+            Err(Error::SchemaVersion(2)) => {}
+            other => panic!("Expected SchemaVersion error, got {:?}", other),
+        }
+        for bad in &[r#""2""#, "2.0", "-1", "null"] {
+            let json = format!(r#"{{"version": {}, "name": "bad", "sections": []}}"#, bad);
+            match Quiz::from_json(&json) {
+                Err(Error::Unexpected(message)) => assert!(message.contains(bad)),
+                other => panic!("Expected an error for version {}, got {:?}", bad, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_json_matches_schema() {
+        let schema: serde_json::Value = serde_json::from_str(&json_schema()).unwrap();
+        let schema = jsonschema::JSONSchema::compile(&schema).unwrap();
+
+        let mut quiz = process_quiz_str(
+            "## First?\n\n1. [x] Yes\n2. [ ] No\n\n## Second?\n\n- [ ] ``a``\n- [x] $b$\n",
+            "cs101/schema",
+            None,
+        )
+        .unwrap();
+        quiz.title = Some("Schema".to_owned());
+        quiz.time_limit_minutes = Some(5);
+        let output: serde_json::Value = serde_json::from_str(&quiz.to_json().unwrap()).unwrap();
+        assert!(schema.is_valid(&output));
+
+        let missing_options = serde_json::json!({
+            "name": "bad",
+            "sections": [{"questions": [{"prompt": "<p>Q</p>"}]}]
+        });
+        assert!(!schema.is_valid(&missing_options));
+    }
+
+    #[test]
+    fn test_markdown_kept() {
+        let qs = process_questions_str(
+            "## Which is *smallest*?\n\nHint: $x_1 < x_2$.\n\n- [x] $x_1$\n- [ ] ``x_2``, which\n  wraps\n",
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            "## Which is *smallest*?\n\nHint: $x_1 < x_2$.",
+            qs[0].prompt_markdown
+        );
+        assert_eq!("$x_1$", qs[0].options[0].content_markdown);
+        assert_eq!("``x_2``, which\nwraps", qs[0].options[1].content_markdown);
+    }
+
+    #[test]
+    fn test_explanation() {
+        let qs = process_questions_str(
+            "## Which sorts in place?\n\n- [x] ``list.sort()``\n- [ ] ``sorted(list)``\n\nThe other returns a *new* list.\n",
+            None,
+        )
+        .unwrap();
+        assert_eq!(2, qs[0].options.len());
+        assert_eq!(
+            "<p>The other returns a <em>new</em> list.</p>\n",
+            qs[0].explanation
+        );
+        assert_eq!(
+            "The other returns a *new* list.",
+            qs[0].explanation_markdown
+        );
+        assert!(!qs[0].prompt.contains("returns"));
+    }
+
+    #[test]
+    fn test_multi_part() {
+        let qs = process_questions_str(
+            "## Trees\n\nA tree has 5 nodes.\n\nHow many edges?\n\n- [x] 4\n- [ ] 5\n\nHow many roots?\n\n- [x] 1\n- [ ] 2\n\nEvery node but the root has one parent.\n",
+            None,
+        )
+        .unwrap();
+        assert_eq!(2, qs.len());
+        assert_eq!(qs[0].stem, qs[1].stem);
+        assert_eq!("<h2>Trees</h2><p>A tree has 5 nodes.</p>\n", qs[0].stem);
+        assert_eq!("## Trees\n\nA tree has 5 nodes.", qs[0].stem_markdown);
+        assert_eq!("<p>How many edges?</p>\n", qs[0].prompt);
+        assert_eq!("How many roots?", qs[1].prompt_markdown);
+        assert_eq!(Some(Part { index: 1, count: 2 }), qs[1].part);
+        assert!(qs[0].explanation.is_empty());
+        assert_eq!(
+            "Every node but the root has one parent.",
+            qs[1].explanation_markdown
+        );
+    }
+
+    #[test]
+    fn test_split_options() {
+        let config = Config {
+            split: SplitOptions {
+                question_level: Some(2),
+                rules: true,
+                preamble: Preamble::Description,
+            },
+            ..Config::default()
+        };
+        let quiz = process_quiz_str(
+            "Practice for *week 1*.\n\n# Lists\n\n## A?\n\n### Hint\n\n- [x] Yes\n\n---\n\nB?\n\n- [x] Yes\n\n# Loops\n\n## C?\n\n- [x] Yes\n",
+            "ex",
+            Some(config),
+        )
+        .unwrap();
+        assert_eq!(
+            Some("<p>Practice for <em>week 1</em>.</p>\n"),
+            quiz.description.as_deref()
+        );
+        assert_eq!(2, quiz.sections.len());
+        assert_eq!(Some("Lists"), quiz.sections[0].title.as_deref());
+        assert_eq!(2, quiz.sections[0].questions.len());
+        assert!(quiz.sections[0].questions[0]
+            .prompt
+            .contains("<h3>Hint</h3>"));
+        assert_eq!("<p>B?</p>\n", quiz.sections[0].questions[1].prompt);
+        assert_eq!(Some("Loops"), quiz.sections[1].title.as_deref());
+    }
+
+    #[test]
+    fn test_section_intro() {
+        let split = SplitOptions {
+            question_level: Some(2),
+            ..SplitOptions::default()
+        };
+        for preamble in [Preamble::Question, Preamble::Description] {
+            let config = Config {
+                split: SplitOptions {
+                    preamble,
+                    ..split.clone()
+                },
+                ..Config::default()
+            };
+            let quiz = process_quiz_str(
+                "# Week 1\n\nPractice for week 1.\n\n## A?\n- [x] y\n\n# Week 2\n\nMore.\n\n- a list\n\n## B?\n- [x] y\n",
+                "ex",
+                Some(config),
+            )
+            .unwrap();
+            assert_eq!(None, quiz.description);
+            assert_eq!(2, quiz.sections.len());
+            assert_eq!(Some("Week 1"), quiz.sections[0].title.as_deref());
+            assert_eq!(
+                Some("<p>Practice for week 1.</p>\n"),
+                quiz.sections[0].description.as_deref()
+            );
+            assert_eq!(1, quiz.sections[0].questions.len());
+            assert_eq!(
+                Some("<p>More.</p>\n<ul>\n<li>a list</li>\n</ul>\n"),
+                quiz.sections[1].description.as_deref()
+            );
+            assert_eq!("## B?", quiz.sections[1].questions[0].prompt_markdown);
+        }
+
+        // Text with options after a section heading is still a question without a heading:
+        let config = Config {
+            split,
+            ..Config::default()
+        };
+        let quiz = process_quiz_str("# Week 1\n\nA?\n\n- [x] y\n", "ex", Some(config)).unwrap();
+        assert_eq!(None, quiz.sections[0].description);
+        assert_eq!("<p>A?</p>\n", quiz.sections[0].questions[0].prompt);
+    }
+
     #[test]
     fn test_broken_q() {
         let broken_q = r#"
@@ -159,4 +814,37 @@ mod tests {
             other => panic!("Expected NoOptionsFound error, got {:?}", other),
         }
     }
+
+    #[test]
+    fn test_code_block_transforms() {
+        let md = "## Which?\n\n```dot\ndigraph { a -> ; }\n```\n\n- [x] A\n- [ ] B\n";
+        let mut compiler = Compiler::new(Config::default()).unwrap();
+        match compiler.process_str(md).unwrap_err() {
+            Error::DiagramError { line, column, .. } => assert_eq!((3, 1), (line, column)),
+            other => panic!("Expected DiagramError, got {:?}", other),
+        }
+
+        compiler
+            .set_code_block_transform("dot", |source| Ok(format!("<pre>{}</pre>", source.len())));
+        let qs = compiler.process_str(md).unwrap();
+        assert!(qs[0].prompt.contains("<pre>19</pre>"));
+
+        let bad_hl = "## Which?\n\n  ```python {hl=5-1}\n  x = 1\n  ```\n\n- [x] A\n- [ ] B\n";
+        match compiler.process_str(bad_hl).unwrap_err() {
+            Error::UnexpectedMarkdown {
+                found,
+                line,
+                column,
+                ..
+            } => {
+                assert_eq!((3, 3), (line, column));
+                assert!(found.contains("5-1"));
+            }
+            other => panic!("Expected UnexpectedMarkdown, got {:?}", other),
+        }
+
+        compiler.remove_code_block_transform("dot");
+        let qs = compiler.process_str(md).unwrap();
+        assert!(qs[0].prompt.contains("digraph"));
+    }
 }
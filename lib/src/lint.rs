@@ -0,0 +1,385 @@
+use crate::{Compiler, Error, Position, Question};
+use pulldown_cmark::{CodeBlockKind, Event, Options, Parser, Tag};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::Path;
+
+/// How seriously ``lint`` takes a rule.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    /// Don't check.
+    Allow,
+    /// Report, but don't fail.
+    Warn,
+    /// Report and fail, e.g., in CI.
+    Deny,
+}
+
+/// A check ``lint`` can run.
+#[derive(Debug, Clone, Copy)]
+pub struct Rule {
+    pub name: &'static str,
+    /// Unless ``LintOptions::levels`` says otherwise.
+    pub default: Level,
+    pub description: &'static str,
+}
+
+pub const RULES: &[Rule] = &[
+    Rule {
+        name: "duplicate-option",
+        default: Level::Warn,
+        description: "Two options in one question say the same thing.",
+    },
+    Rule {
+        name: "all-correct",
+        default: Level::Warn,
+        description: "Every option is correct.",
+    },
+    Rule {
+        name: "single-correct",
+        default: Level::Allow,
+        description: "Exactly one option is correct, but students may select several.",
+    },
+    Rule {
+        name: "above-shuffled",
+        default: Level::Warn,
+        description:
+            "An option like \"none of the above\" in a question whose options get shuffled.",
+    },
+    Rule {
+        name: "duplicate-prompt",
+        default: Level::Warn,
+        description: "Two questions, maybe in different files, ask the same thing.",
+    },
+    Rule {
+        name: "empty-option",
+        default: Level::Deny,
+        description: "An option with no text.",
+    },
+    Rule {
+        name: "option-length",
+        default: Level::Warn,
+        description: "One option is much longer than another, which can give the answer away.",
+    },
+    Rule {
+        name: "untagged-code",
+        default: Level::Warn,
+        description:
+            "A code block without a language, so it is highlighted as the default language.",
+    },
+];
+
+/// Options shorter than this are never too long for ``option-length``.
+const LONG_OPTION: usize = 20;
+
+/// Which rules ``lint`` runs; the ``[lint]`` table of a manifest.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct LintOptions {
+    /// Levels by rule name, overriding each rule's default.
+    pub levels: BTreeMap<String, Level>,
+    /// ``option-length`` fires when the longest option has this many times the characters of the shortest.
+    pub length_ratio: f64,
+}
+
+impl Default for LintOptions {
+    fn default() -> Self {
+        Self {
+            levels: BTreeMap::new(),
+            length_ratio: 4.0,
+        }
+    }
+}
+
+impl LintOptions {
+    pub fn level(&self, rule: &str) -> Level {
+        match self.levels.get(rule) {
+            Some(level) => *level,
+            None => RULES
+                .iter()
+                .find(|r| r.name == rule)
+                .map(|r| r.default)
+                .unwrap_or(Level::Allow),
+        }
+    }
+}
+
+/// One problem found by ``lint``.
+#[derive(Debug, Clone)]
+pub struct Lint {
+    pub rule: &'static str,
+    pub level: Level,
+    pub file: String,
+    pub position: Position,
+    pub message: String,
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}: {}: {} [{}]",
+            self.file,
+            self.position.line,
+            self.position.column,
+            if self.level == Level::Deny {
+                "error"
+            } else {
+                "warning"
+            },
+            self.message,
+            self.rule
+        )
+    }
+}
+
+/// Collects lints for one file, skipping allowed rules.
+struct Reporter<'a> {
+    options: &'a LintOptions,
+    file: &'a str,
+    found: Vec<Lint>,
+}
+
+impl<'a> Reporter<'a> {
+    fn report(&mut self, rule: &'static str, position: Option<Position>, message: String) {
+        let level = self.options.level(rule);
+        if level != Level::Allow {
+            self.found.push(Lint {
+                rule,
+                level,
+                file: self.file.to_owned(),
+                position: position.unwrap_or_default(),
+                message,
+            });
+        }
+    }
+}
+
+/// Whitespace- and case-insensitive text, for comparing options and prompts.
+fn normalize(markdown: &str) -> String {
+    markdown
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+fn check_question(q: &Question, length_ratio: f64, out: &mut Reporter) {
+    let texts: Vec<String> = q
+        .options
+        .iter()
+        .map(|opt| normalize(&opt.content_markdown))
+        .collect();
+    for (i, opt) in q.options.iter().enumerate() {
+        if texts[i].is_empty() {
+            out.report("empty-option", opt.position, "Option is empty.".to_owned());
+        } else if texts[..i].contains(&texts[i]) {
+            out.report(
+                "duplicate-option",
+                opt.position,
+                format!("Option repeats an earlier one: {:?}", opt.content_markdown),
+            );
+        }
+        if !q.ordered && texts[i].contains("of the above") {
+            out.report(
+                "above-shuffled",
+                opt.position,
+                format!(
+                    "{:?} depends on order, but options are shuffled; use a numbered list to keep them in place.",
+                    opt.content_markdown
+                ),
+            );
+        }
+    }
+
+    let correct = q.options.iter().filter(|opt| opt.correct).count();
+    if q.options.len() > 1 && correct == q.options.len() {
+        out.report(
+            "all-correct",
+            q.position,
+            "Every option is correct.".to_owned(),
+        );
+    }
+    if q.options.len() > 1 && correct == 1 {
+        out.report(
+            "single-correct",
+            q.position,
+            "Only one option is correct, but students may select several.".to_owned(),
+        );
+    }
+
+    let lengths = texts
+        .iter()
+        .filter(|t| !t.is_empty())
+        .map(|t| t.chars().count());
+    if let (Some(shortest), Some(longest)) = (lengths.clone().min(), lengths.max()) {
+        if longest >= LONG_OPTION && longest as f64 >= length_ratio * shortest as f64 {
+            out.report(
+                "option-length",
+                q.position,
+                format!(
+                    "Options range from {} to {} characters; the odd one out may give the answer away.",
+                    shortest, longest
+                ),
+            );
+        }
+    }
+}
+
+/// Code blocks with no language in ``markdown``.
+fn check_code_blocks(markdown: &str, default_lang: &str, out: &mut Reporter) {
+    let mut md_opt = Options::empty();
+    md_opt.insert(Options::ENABLE_TABLES);
+    md_opt.insert(Options::ENABLE_TASKLISTS);
+    for (event, range) in Parser::new_ext(markdown, md_opt).into_offset_iter() {
+        let untagged = match &event {
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Indented)) => true,
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))) => info.trim().is_empty(),
+            _ => false,
+        };
+        if untagged {
+            let (line, column) = crate::media::line_column(markdown, range.start);
+            out.report(
+                "untagged-code",
+                Some(Position { line, column }),
+                format!(
+                    "Code block has no language, so it is highlighted as {:?}.",
+                    default_lang
+                ),
+            );
+        }
+    }
+}
+
+/// Check ``sources``, (file name, markdown) pairs, for questions that are likely to confuse students.
+/// Images are found relative to each file name; a file that doesn't parse is an error.
+pub fn lint(
+    compiler: &Compiler,
+    sources: &[(String, String)],
+    options: &LintOptions,
+) -> Result<Vec<Lint>, Error> {
+    for name in options.levels.keys() {
+        if !RULES.iter().any(|r| r.name == name.as_str()) {
+            return Err(Error::Unexpected(format!("Unknown lint rule {:?}", name)));
+        }
+    }
+    let default_lang = &compiler.config().syntax.default_lang;
+    let mut found = Vec::new();
+    // First place each prompt was seen:
+    let mut prompts: HashMap<String, (String, Position)> = HashMap::new();
+    for (file, markdown) in sources.iter() {
+        let questions = compiler
+            .process(markdown, Path::new(file).parent())
+            .map_err(|e| Error::Unexpected(format!("{}: {}", file, e)))?
+            .into_questions();
+        let mut out = Reporter {
+            options,
+            file,
+            found: Vec::new(),
+        };
+        for q in questions.iter() {
+            check_question(q, options.length_ratio, &mut out);
+            let prompt = normalize(&format!("{}\n{}", q.stem_markdown, q.prompt_markdown));
+            let position = q.position.unwrap_or_default();
+            match prompts.get(&prompt) {
+                Some((first_file, first)) => out.report(
+                    "duplicate-prompt",
+                    q.position,
+                    format!(
+                        "Same prompt as {}:{}:{}.",
+                        first_file, first.line, first.column
+                    ),
+                ),
+                None => {
+                    prompts.insert(prompt, (file.clone(), position));
+                }
+            }
+        }
+        check_code_blocks(markdown, default_lang, &mut out);
+        out.found
+            .sort_by_key(|l| (l.position.line, l.position.column));
+        found.extend(out.found);
+    }
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+
+    fn lint_str(markdown: &str, options: &LintOptions) -> Vec<Lint> {
+        let compiler = Compiler::new(Config::default()).unwrap();
+        lint(
+            &compiler,
+            &[("quiz.md".to_owned(), markdown.to_owned())],
+            options,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_rules() {
+        let found = lint_str(
+            "## A?\n\n- [x] Yes\n- [x] yes\n- [ ] None of the above\n\n## B?\n\n```\nx = 1\n```\n\n- [x] A\n- [ ] A much, much longer option\n",
+            &LintOptions::default(),
+        );
+        let rules: Vec<(&str, usize, usize)> = found
+            .iter()
+            .map(|l| (l.rule, l.position.line, l.position.column))
+            .collect();
+        assert_eq!(
+            vec![
+                ("duplicate-option", 4, 3),
+                ("above-shuffled", 5, 3),
+                ("option-length", 7, 1),
+                ("untagged-code", 9, 1),
+            ],
+            rules
+        );
+        assert_eq!(
+            "quiz.md:4:3: warning: Option repeats an earlier one: \"yes\" [duplicate-option]",
+            found[0].to_string()
+        );
+    }
+
+    #[test]
+    fn test_levels_and_duplicate_prompts() {
+        let mut options = LintOptions::default();
+        options
+            .levels
+            .insert("single-correct".to_owned(), Level::Deny);
+        options
+            .levels
+            .insert("untagged-code".to_owned(), Level::Allow);
+        let compiler = Compiler::new(Config::default()).unwrap();
+        let q = "## Pick one\n\n- [x] A\n- [ ] B\n";
+        let found = lint(
+            &compiler,
+            &[
+                ("a.md".to_owned(), q.to_owned()),
+                ("b.md".to_owned(), format!("\n{}", q)),
+            ],
+            &options,
+        )
+        .unwrap();
+        let rules: Vec<(&str, &str, Level)> = found
+            .iter()
+            .map(|l| (l.file.as_str(), l.rule, l.level))
+            .collect();
+        assert_eq!(
+            vec![
+                ("a.md", "single-correct", Level::Deny),
+                ("b.md", "single-correct", Level::Deny),
+                ("b.md", "duplicate-prompt", Level::Warn),
+            ],
+            rules
+        );
+        assert_eq!("Same prompt as a.md:1:1.", found[2].message);
+
+        options
+            .levels
+            .insert("no-such-rule".to_owned(), Level::Warn);
+        assert!(lint(&compiler, &[], &options).is_err());
+    }
+}
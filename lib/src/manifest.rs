@@ -0,0 +1,407 @@
+use crate::balance::balance;
+use crate::lint::LintOptions;
+use crate::media::local_images;
+use crate::{stylesheet_path, Compiler, Config, Error, OutputFormat};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// The file name ``quizdown build`` looks for by default.
+pub const DEFAULT_MANIFEST: &str = "quizdown.toml";
+
+/// A project manifest: many markdown inputs and the outputs to build from each.
+///
+/// ```toml
+/// formats = ["moodle", "html"]
+/// output_dir = "build"
+///
+/// [config.syntax]
+/// default_lang = "python"
+///
+/// [[quiz]]
+/// input = "lectures/01_intro.md"
+/// name = "cs101/01_intro"
+/// title = "Introduction"
+/// time_limit_minutes = 15
+/// balance_seed = 2024
+///
+/// [[quiz]]
+/// input = "lectures/02_lists.md"
+/// formats = ["moodle"]
+///
+/// [lint.levels]
+/// single-correct = "Warn"
+/// ```
+///
+/// All paths are relative to the directory containing the manifest.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Manifest {
+    /// Formats to build for any quiz that doesn't list its own, e.g., "html", "moodle", "json".
+    pub formats: Vec<String>,
+    /// Where outputs go unless a quiz says otherwise; by default, next to each input.
+    pub output_dir: Option<String>,
+    /// Parsing and highlighting options shared by every quiz.
+    pub config: Config,
+    /// Rules for ``quizdown lint``.
+    pub lint: LintOptions,
+    #[serde(rename = "quiz")]
+    pub quizzes: Vec<ManifestQuiz>,
+    /// Directory that relative paths are resolved against.
+    #[serde(skip)]
+    root: PathBuf,
+    /// When the manifest itself last changed; editing it rebuilds everything.
+    #[serde(skip)]
+    modified: Option<SystemTime>,
+}
+
+/// One ``[[quiz]]`` entry in a manifest.
+#[derive(Deserialize, Debug, Clone)]
+pub struct ManifestQuiz {
+    /// The markdown file to read.
+    pub input: String,
+    /// The quiz name or question category; by default the input file name without extension.
+    pub name: Option<String>,
+    /// Overrides the manifest-wide formats.
+    pub formats: Option<Vec<String>>,
+    /// Overrides the manifest-wide output directory.
+    pub output_dir: Option<String>,
+    /// Shown to students; by default the name.
+    pub title: Option<String>,
+    /// The Moodle question category; by default the name.
+    pub category: Option<String>,
+    pub time_limit_minutes: Option<u32>,
+    /// Spread correct answers over positions with ``balance::balance`` and this seed.
+    pub balance_seed: Option<u64>,
+    /// See ``Quiz::hide_answer_count``.
+    #[serde(default)]
+    pub hide_answer_count: bool,
+}
+
+/// A single file that ``Manifest::build`` will produce.
+#[derive(Debug, Clone)]
+pub struct BuildTarget {
+    pub input: PathBuf,
+    pub name: String,
+    pub title: Option<String>,
+    pub category: Option<String>,
+    pub time_limit_minutes: Option<u32>,
+    pub balance_seed: Option<u64>,
+    pub hide_answer_count: bool,
+    pub format: OutputFormat,
+    pub output: PathBuf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildStatus {
+    /// The output was (re-)rendered.
+    Built,
+    /// The output was newer than the manifest and everything it is built from, so we left it alone.
+    UpToDate,
+}
+
+impl Manifest {
+    pub fn load(path: &str) -> Result<Manifest, Error> {
+        let path = Path::new(path);
+        let contents = fs::read_to_string(path)?;
+        let root = path.parent().unwrap_or_else(|| Path::new("."));
+        let mut manifest = Manifest::parse(&contents, root)?;
+        manifest.modified = fs::metadata(path)?.modified().ok();
+        Ok(manifest)
+    }
+
+    pub fn parse(contents: &str, root: &Path) -> Result<Manifest, Error> {
+        let mut manifest: Manifest = toml::from_str(contents)?;
+        manifest.root = root.to_path_buf();
+        let syntax = &mut manifest.config.syntax;
+        for dir in syntax
+            .syntax_dirs
+            .iter_mut()
+            .chain(syntax.theme_dirs.iter_mut())
+        {
+            *dir = root.join(&dir).to_string_lossy().to_string();
+        }
+        Ok(manifest)
+    }
+
+    /// Every markdown input listed in this manifest, in order.
+    pub fn inputs(&self) -> Vec<PathBuf> {
+        self.quizzes
+            .iter()
+            .map(|quiz| self.root.join(&quiz.input))
+            .collect()
+    }
+
+    /// Every (input, format) pair listed in this manifest, in order.
+    /// Two quizzes that would write the same output file are an error, rather than one silently winning.
+    pub fn targets(&self) -> Result<Vec<BuildTarget>, Error> {
+        let mut targets = Vec::new();
+        let mut claimed: HashMap<PathBuf, &str> = HashMap::new();
+        for quiz in self.quizzes.iter() {
+            let input = self.root.join(&quiz.input);
+            let stem = input
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_else(|| quiz.input.clone());
+            let name = quiz.name.clone().unwrap_or_else(|| stem.clone());
+            let output_dir = match quiz.output_dir.as_ref().or(self.output_dir.as_ref()) {
+                Some(dir) => self.root.join(dir),
+                None => input.parent().map(Path::to_path_buf).unwrap_or_default(),
+            };
+            let formats = quiz.formats.as_ref().unwrap_or(&self.formats);
+            if formats.is_empty() {
+                return Err(Error::Unexpected(format!(
+                    "No output formats listed for {:?}",
+                    quiz.input
+                )));
+            }
+            for format in formats.iter() {
+                let format = OutputFormat::from_short_name(format)
+                    .ok_or_else(|| Error::UnknownFormat(format.clone()))?;
+                let output = output_dir.join(format!("{}.{}", stem, format.extension()));
+                if let Some(other) = claimed.insert(output.clone(), &quiz.input) {
+                    return Err(Error::Unexpected(format!(
+                        "Both {:?} and {:?} would be built to {:?}; give one its own output_dir",
+                        other, quiz.input, output
+                    )));
+                }
+                targets.push(BuildTarget {
+                    input: input.clone(),
+                    name: name.clone(),
+                    title: quiz.title.clone(),
+                    category: quiz.category.clone(),
+                    time_limit_minutes: quiz.time_limit_minutes,
+                    balance_seed: quiz.balance_seed,
+                    hide_answer_count: quiz.hide_answer_count,
+                    format,
+                    output,
+                });
+            }
+        }
+        Ok(targets)
+    }
+
+    /// Every file that ``target``'s output depends on: its input, the images that refers to, and any syntax or
+    /// theme files loaded from disk.
+    fn dependencies(&self, target: &BuildTarget) -> Vec<PathBuf> {
+        let mut found = vec![target.input.clone()];
+        let is_json = target
+            .input
+            .extension()
+            .map(|e| e == "json")
+            .unwrap_or(false);
+        if let (false, Ok(markdown)) = (is_json, fs::read_to_string(&target.input)) {
+            let base = target.input.parent().unwrap_or_else(|| Path::new("."));
+            found.extend(local_images(&markdown).iter().map(|dest| base.join(dest)));
+        }
+        let syntax = &self.config.syntax;
+        for dir in syntax.syntax_dirs.iter().chain(syntax.theme_dirs.iter()) {
+            list_files(Path::new(dir), &mut found);
+        }
+        found
+    }
+
+    /// Whether ``target``'s output (and its ``.css`` file, if it gets one) is newer than the manifest and every one of
+    /// its ``dependencies``.
+    fn is_up_to_date(&self, target: &BuildTarget, has_stylesheet: bool) -> bool {
+        let modified = |p: &Path| fs::metadata(p).and_then(|m| m.modified()).ok();
+        let mut outputs = vec![target.output.clone()];
+        if has_stylesheet && !target.format.is_full_page() {
+            outputs.push(stylesheet_path(&target.output));
+        }
+        // ``None`` sorts first, so a missing output makes this ``None``:
+        let output_time = match outputs.iter().map(|p| modified(p)).min().flatten() {
+            Some(t) => t,
+            None => return false,
+        };
+        // A missing image is rebuilt, so that the build reports it:
+        let inputs_ok = self
+            .dependencies(target)
+            .iter()
+            .all(|p| modified(p).map(|t| output_time >= t).unwrap_or(false));
+        let manifest_ok = match self.modified {
+            Some(m) => output_time >= m,
+            None => true,
+        };
+        inputs_ok && manifest_ok
+    }
+
+    /// Render every target, skipping those whose outputs are already newer than their inputs (unless ``force``).
+    /// Each input is parsed at most once, no matter how many formats it is built into.
+    pub fn build(&self, force: bool) -> Result<Vec<(BuildTarget, BuildStatus)>, Error> {
+        let mut results = Vec::new();
+        let targets = self.targets()?;
+        let stylesheet = self.config.syntax.stylesheet()?;
+        let compiler = Compiler::new(self.config.clone())?;
+        let mut i = 0;
+        while i < targets.len() {
+            // targets() lists all formats for an input together:
+            let mut j = i;
+            while j < targets.len() && targets[j].input == targets[i].input {
+                j += 1;
+            }
+            let group = &targets[i..j];
+            i = j;
+
+            if !force
+                && group
+                    .iter()
+                    .all(|t| self.is_up_to_date(t, stylesheet.is_some()))
+            {
+                for t in group {
+                    results.push((t.clone(), BuildStatus::UpToDate));
+                }
+                continue;
+            }
+
+            let first = &group[0];
+            let mut quiz =
+                compiler.quiz_from_file(&first.input.to_string_lossy(), Some(&first.name))?;
+            quiz.title = first.title.clone();
+            quiz.category = first.category.clone();
+            quiz.time_limit_minutes = first.time_limit_minutes;
+            quiz.hide_answer_count = first.hide_answer_count;
+            if let Some(seed) = first.balance_seed {
+                balance(&mut quiz, seed);
+            }
+            for t in group {
+                let output = t
+                    .format
+                    .render_with_stylesheet(&quiz, stylesheet.as_deref())?;
+                if let Some(dir) = t.output.parent() {
+                    fs::create_dir_all(dir)?;
+                }
+                fs::write(&t.output, output)?;
+                if let Some(css) = stylesheet.as_ref().filter(|_| !t.format.is_full_page()) {
+                    fs::write(stylesheet_path(&t.output), css)?;
+                }
+                results.push((t.clone(), BuildStatus::Built));
+            }
+        }
+        Ok(results)
+    }
+}
+
+/// Every file under ``dir``, at any depth; syntect loads syntaxes and themes the same way.
+fn list_files(dir: &Path, found: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            list_files(&path, found);
+        } else {
+            found.push(path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn test_targets() {
+        let manifest = Manifest::parse(
+            r#"
+formats = ["moodle", "html"]
+output_dir = "build"
+
+[config.syntax]
+default_lang = "python"
+
+[[quiz]]
+input = "lectures/01_intro.md"
+name = "cs101/intro"
+title = "Introduction"
+
+[[quiz]]
+input = "02_lists.md"
+formats = ["json"]
+output_dir = "json"
+        "#,
+            Path::new("course"),
+        )
+        .unwrap();
+        assert_eq!("python", manifest.config.syntax.default_lang);
+        assert_eq!("InspiredGitHub", manifest.config.syntax.theme);
+
+        let targets = manifest.targets().unwrap();
+        assert_eq!(3, targets.len());
+        assert_eq!("cs101/intro", targets[0].name);
+        assert_eq!(Some("Introduction"), targets[1].title.as_deref());
+        assert_eq!(None, targets[2].title);
+        assert_eq!(OutputFormat::MoodleXml, targets[0].format);
+        assert_eq!(Path::new("course/build/01_intro.moodle"), targets[0].output);
+        assert_eq!(Path::new("course/build/01_intro.html"), targets[1].output);
+        assert_eq!("02_lists", targets[2].name);
+        assert_eq!(Path::new("course/json/02_lists.json"), targets[2].output);
+    }
+
+    #[test]
+    fn test_unknown_format() {
+        let manifest = Manifest::parse(
+            "formats = [\"pdf\"]\n[[quiz]]\ninput = \"a.md\"\n",
+            Path::new("."),
+        )
+        .unwrap();
+        match manifest.targets().unwrap_err() {
+            Error::UnknownFormat(f) => assert_eq!("pdf", f),
+            other => panic!("Expected UnknownFormat error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_duplicate_outputs() {
+        let manifest = Manifest::parse(
+            "formats = [\"moodle\"]\noutput_dir = \".\"\n[[quiz]]\ninput = \"m.md\"\n[[quiz]]\ninput = \"a/m.md\"\n",
+            Path::new("."),
+        )
+        .unwrap();
+        match manifest.targets().unwrap_err() {
+            Error::Unexpected(msg) => assert!(msg.contains("\"a/m.md\""), "{}", msg),
+            other => panic!("Expected Unexpected error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_build_skips_unchanged() {
+        let dir = TempDir::new("manifest");
+        dir.write(
+            "colors.md",
+            b"## Favorite color?\n\n![swatch](red.png)\n\n- [x] red\n- [ ] blue\n",
+        );
+        dir.write("red.png", b"red");
+        let manifest = Manifest::parse(
+            "formats = [\"moodle\", \"json\"]\noutput_dir = \"out\"\n[[quiz]]\ninput = \"colors.md\"\n",
+            &dir,
+        )
+        .unwrap();
+
+        let first = manifest.build(false).unwrap();
+        assert!(first.iter().all(|(_, s)| *s == BuildStatus::Built));
+        assert!(dir.join("out/colors.moodle").exists());
+        assert!(dir.join("out/colors.json").exists());
+
+        let second = manifest.build(false).unwrap();
+        assert!(second.iter().all(|(_, s)| *s == BuildStatus::UpToDate));
+
+        let forced = manifest.build(true).unwrap();
+        assert!(forced.iter().all(|(_, s)| *s == BuildStatus::Built));
+
+        // A changed image counts as a changed input:
+        let later = SystemTime::now() + std::time::Duration::from_secs(3600);
+        fs::File::options()
+            .write(true)
+            .open(dir.join("red.png"))
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        let after_image = manifest.build(false).unwrap();
+        assert!(after_image.iter().all(|(_, s)| *s == BuildStatus::Built));
+    }
+}
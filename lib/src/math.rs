@@ -0,0 +1,235 @@
+use crate::mathml::escape;
+use pulldown_cmark::{CowStr, Event, Options, Parser};
+use std::ops::Range;
+
+/// Math spans are swapped for one private-use character (``FIRST_PLACEHOLDER + index``) padded to the original length,
+/// so byte offsets from the markdown parser still point into the author's file.
+const FIRST_PLACEHOLDER: u32 = 0xE000;
+const LAST_PLACEHOLDER: u32 = 0xF8FF;
+const PADDING: char = 'x';
+
+/// A ``$...$`` or ``$$...$$`` span found in the markdown source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct MathSpan {
+    pub(crate) tex: String,
+    pub(crate) display: bool,
+    /// Length in bytes of the original span, delimiters included.
+    len: usize,
+}
+
+impl MathSpan {
+    /// MathJax delimiters, which Moodle's TeX filters understand too.
+    fn to_html(&self) -> String {
+        if self.display {
+            format!("\\[{}\\]", escape(&self.tex))
+        } else {
+            format!("\\({}\\)", escape(&self.tex))
+        }
+    }
+}
+
+fn placeholder_index(c: char) -> Option<usize> {
+    let c = c as u32;
+    if (FIRST_PLACEHOLDER..=LAST_PLACEHOLDER).contains(&c) {
+        Some((c - FIRST_PLACEHOLDER) as usize)
+    } else {
+        None
+    }
+}
+
+/// Code (inline or block) and raw HTML are never math.
+fn code_ranges(content: &str, md_opt: Options) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut block_start = None;
+    for (event, range) in Parser::new_ext(content, md_opt).into_offset_iter() {
+        match event {
+            Event::Start(pulldown_cmark::Tag::CodeBlock(_)) => block_start = Some(range.start),
+            Event::End(pulldown_cmark::Tag::CodeBlock(_)) => {
+                ranges.push(block_start.take().unwrap_or(range.start)..range.end)
+            }
+            Event::Code(_) | Event::Html(_) => ranges.push(range),
+            _ => {}
+        }
+    }
+    ranges
+}
+
+/// Find math in ``content`` and hide it from the markdown parser, which would otherwise read ``_`` and ``*`` as emphasis.
+///
+/// Display math is ``$$...$$`` and may cross lines. Inline math is ``$...$`` on one line, where the opening ``$`` is not
+/// followed by a space and the closing one is not preceded by a space or followed by a digit, so "$5 and $10" is left alone.
+pub(crate) fn protect_math(content: &str, md_opt: Options) -> (String, Vec<MathSpan>) {
+    let code = code_ranges(content, md_opt);
+    let in_code = |i: usize| code.iter().any(|r| r.contains(&i));
+    let bytes = content.as_bytes();
+    let mut spans = Vec::new();
+    let mut output = String::with_capacity(content.len());
+    let mut copied = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let index = spans.len() as u32;
+        if bytes[i] == b'\\' {
+            i += 2;
+            continue;
+        }
+        if bytes[i] != b'$' || in_code(i) || FIRST_PLACEHOLDER + index > LAST_PLACEHOLDER {
+            i += 1;
+            continue;
+        }
+        let display = bytes.get(i + 1) == Some(&b'$');
+        let found = if display {
+            find_display_end(content, i + 2).map(|end| (i + 2..end, end + 2))
+        } else {
+            find_inline_end(content, i + 1).map(|end| (i + 1..end, end + 1))
+        };
+        let (tex, end) = match found {
+            Some(found) if !code.iter().any(|r| r.contains(&(found.1 - 1))) => found,
+            _ => {
+                i += if display { 2 } else { 1 };
+                continue;
+            }
+        };
+        let span = MathSpan {
+            tex: content[tex].trim().to_owned(),
+            display,
+            len: end - i,
+        };
+        output.push_str(&content[copied..i]);
+        let placeholder = std::char::from_u32(FIRST_PLACEHOLDER + index).unwrap();
+        output.push(placeholder);
+        for _ in placeholder.len_utf8()..span.len {
+            output.push(PADDING);
+        }
+        spans.push(span);
+        copied = end;
+        i = end;
+    }
+    output.push_str(&content[copied..]);
+    (output, spans)
+}
+
+fn find_display_end(content: &str, from: usize) -> Option<usize> {
+    let bytes = content.as_bytes();
+    let mut i = from;
+    while i + 1 < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'$' if bytes[i + 1] == b'$' => {
+                return if content[from..i].trim().is_empty() {
+                    None
+                } else {
+                    Some(i)
+                };
+            }
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+fn find_inline_end(content: &str, from: usize) -> Option<usize> {
+    let bytes = content.as_bytes();
+    match bytes.get(from) {
+        None | Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'$') => return None,
+        _ => {}
+    }
+    let mut i = from;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\n' => return None,
+            b'\\' => i += 2,
+            b'$' => {
+                let before = bytes[i - 1];
+                let after = bytes.get(i + 1).cloned().unwrap_or(b' ');
+                if before.is_ascii_whitespace() || after.is_ascii_digit() {
+                    return None;
+                }
+                return Some(i);
+            }
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+/// Undo ``protect_math``: swap placeholders in text events for HTML that MathJax (or Moodle) will typeset.
+pub(crate) fn restore_math<'md, I>(events: I, spans: &[MathSpan]) -> Vec<(Event<'md>, Range<usize>)>
+where
+    I: Iterator<Item = (Event<'md>, Range<usize>)>,
+{
+    let mut output = Vec::new();
+    for (event, range) in events {
+        let text = match &event {
+            Event::Text(text)
+                if !spans.is_empty() && text.chars().any(|c| placeholder_index(c).is_some()) =>
+            {
+                text.to_string()
+            }
+            _ => {
+                output.push((event, range));
+                continue;
+            }
+        };
+        let mut plain = String::new();
+        let mut chars = text.chars();
+        while let Some(c) = chars.next() {
+            let span = match placeholder_index(c).and_then(|i| spans.get(i)) {
+                Some(span) => span,
+                None => {
+                    plain.push(c);
+                    continue;
+                }
+            };
+            for _ in c.len_utf8()..span.len {
+                chars.next();
+            }
+            if !plain.is_empty() {
+                output.push((Event::Text(CowStr::from(plain.clone())), range.clone()));
+                plain.clear();
+            }
+            output.push((Event::Html(CowStr::from(span.to_html())), range.clone()));
+        }
+        if !plain.is_empty() {
+            output.push((Event::Text(CowStr::from(plain)), range.clone()));
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn md_opt() -> Options {
+        let mut md_opt = Options::empty();
+        md_opt.insert(Options::ENABLE_TASKLISTS);
+        md_opt
+    }
+
+    #[test]
+    fn test_protect_math() {
+        let src = "Is $a_1 * b_2 * c$ cheaper than $$\n\\sum_i x_i\n$$ for $5 or $10? `$not$ math`";
+        let (protected, spans) = protect_math(src, md_opt());
+        assert_eq!(src.len(), protected.len());
+        assert_eq!(2, spans.len());
+        assert_eq!("a_1 * b_2 * c", spans[0].tex);
+        assert!(!spans[0].display);
+        assert_eq!("\\sum_i x_i", spans[1].tex);
+        assert!(spans[1].display);
+        assert!(protected.ends_with(" for $5 or $10? `$not$ math`"));
+    }
+
+    #[test]
+    fn test_restore_math() {
+        let src = "Is $a_1 < b_1$ *really* true?";
+        let (protected, spans) = protect_math(src, md_opt());
+        let events = restore_math(
+            Parser::new_ext(&protected, md_opt()).into_offset_iter(),
+            &spans,
+        );
+        let mut html = String::new();
+        pulldown_cmark::html::push_html(&mut html, events.into_iter().map(|(e, _)| e));
+        assert_eq!("<p>Is \\(a_1 &lt; b_1\\) <em>really</em> true?</p>\n", html);
+    }
+}
@@ -0,0 +1,581 @@
+use std::fmt::Write;
+
+const MATHML_NS: &str = "http://www.w3.org/1998/Math/MathML";
+
+pub(crate) fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn unescape(html: &str) -> String {
+    html.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Commands that become a single ``<mi>`` (letters) or ``<mo>`` (everything else).
+fn symbol(name: &str) -> Option<(&'static str, &'static str)> {
+    Some(match name {
+        "alpha" => ("mi", "α"),
+        "beta" => ("mi", "β"),
+        "gamma" => ("mi", "γ"),
+        "delta" => ("mi", "δ"),
+        "epsilon" => ("mi", "ϵ"),
+        "varepsilon" => ("mi", "ε"),
+        "zeta" => ("mi", "ζ"),
+        "eta" => ("mi", "η"),
+        "theta" => ("mi", "θ"),
+        "iota" => ("mi", "ι"),
+        "kappa" => ("mi", "κ"),
+        "lambda" => ("mi", "λ"),
+        "mu" => ("mi", "μ"),
+        "nu" => ("mi", "ν"),
+        "xi" => ("mi", "ξ"),
+        "pi" => ("mi", "π"),
+        "rho" => ("mi", "ρ"),
+        "sigma" => ("mi", "σ"),
+        "tau" => ("mi", "τ"),
+        "phi" => ("mi", "ϕ"),
+        "varphi" => ("mi", "φ"),
+        "chi" => ("mi", "χ"),
+        "psi" => ("mi", "ψ"),
+        "omega" => ("mi", "ω"),
+        "Gamma" => ("mi", "Γ"),
+        "Delta" => ("mi", "Δ"),
+        "Theta" => ("mi", "Θ"),
+        "Lambda" => ("mi", "Λ"),
+        "Xi" => ("mi", "Ξ"),
+        "Pi" => ("mi", "Π"),
+        "Sigma" => ("mi", "Σ"),
+        "Phi" => ("mi", "Φ"),
+        "Psi" => ("mi", "Ψ"),
+        "Omega" => ("mi", "Ω"),
+        "infty" => ("mi", "∞"),
+        "emptyset" | "varnothing" => ("mi", "∅"),
+        "ell" => ("mi", "ℓ"),
+        "partial" => ("mo", "∂"),
+        "nabla" => ("mo", "∇"),
+        "times" => ("mo", "×"),
+        "cdot" => ("mo", "⋅"),
+        "div" => ("mo", "÷"),
+        "pm" => ("mo", "±"),
+        "mp" => ("mo", "∓"),
+        "ast" => ("mo", "∗"),
+        "circ" => ("mo", "∘"),
+        "leq" | "le" => ("mo", "≤"),
+        "geq" | "ge" => ("mo", "≥"),
+        "neq" | "ne" => ("mo", "≠"),
+        "approx" => ("mo", "≈"),
+        "equiv" => ("mo", "≡"),
+        "sim" => ("mo", "∼"),
+        "propto" => ("mo", "∝"),
+        "in" => ("mo", "∈"),
+        "notin" => ("mo", "∉"),
+        "subset" => ("mo", "⊂"),
+        "subseteq" => ("mo", "⊆"),
+        "supset" => ("mo", "⊃"),
+        "supseteq" => ("mo", "⊇"),
+        "cup" => ("mo", "∪"),
+        "cap" => ("mo", "∩"),
+        "setminus" => ("mo", "∖"),
+        "land" | "wedge" => ("mo", "∧"),
+        "lor" | "vee" => ("mo", "∨"),
+        "lnot" | "neg" => ("mo", "¬"),
+        "oplus" => ("mo", "⊕"),
+        "forall" => ("mo", "∀"),
+        "exists" => ("mo", "∃"),
+        "to" | "rightarrow" => ("mo", "→"),
+        "leftarrow" | "gets" => ("mo", "←"),
+        "Rightarrow" | "implies" => ("mo", "⇒"),
+        "Leftarrow" => ("mo", "⇐"),
+        "leftrightarrow" => ("mo", "↔"),
+        "Leftrightarrow" | "iff" => ("mo", "⇔"),
+        "mapsto" => ("mo", "↦"),
+        "sum" => ("mo", "∑"),
+        "prod" => ("mo", "∏"),
+        "int" => ("mo", "∫"),
+        "oint" => ("mo", "∮"),
+        "ldots" | "dots" => ("mo", "…"),
+        "cdots" => ("mo", "⋯"),
+        "vdots" => ("mo", "⋮"),
+        "lfloor" => ("mo", "⌊"),
+        "rfloor" => ("mo", "⌋"),
+        "lceil" => ("mo", "⌈"),
+        "rceil" => ("mo", "⌉"),
+        "langle" => ("mo", "⟨"),
+        "rangle" => ("mo", "⟩"),
+        "mid" => ("mo", "∣"),
+        "vert" => ("mo", "|"),
+        "Vert" => ("mo", "‖"),
+        "{" => ("mo", "{"),
+        "}" => ("mo", "}"),
+        "%" => ("mo", "%"),
+        "$" => ("mo", "$"),
+        "#" => ("mo", "#"),
+        "&" => ("mo", "&"),
+        "_" => ("mo", "_"),
+        "|" => ("mo", "‖"),
+        _ => return None,
+    })
+}
+
+/// Named functions like ``\log`` are upright identifiers.
+fn is_function(name: &str) -> bool {
+    matches!(
+        name,
+        "log"
+            | "ln"
+            | "lg"
+            | "exp"
+            | "sin"
+            | "cos"
+            | "tan"
+            | "sec"
+            | "csc"
+            | "cot"
+            | "arcsin"
+            | "arccos"
+            | "arctan"
+            | "sinh"
+            | "cosh"
+            | "tanh"
+            | "min"
+            | "max"
+            | "inf"
+            | "sup"
+            | "lim"
+            | "gcd"
+            | "det"
+            | "deg"
+            | "dim"
+            | "mod"
+            | "bmod"
+            | "Pr"
+    )
+}
+
+fn space(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "," | "thinspace" => "0.167em",
+        ":" | ">" | "medspace" => "0.222em",
+        ";" | "thickspace" => "0.278em",
+        " " => "0.25em",
+        "quad" => "1em",
+        "qquad" => "2em",
+        "!" => "-0.167em",
+        _ => return None,
+    })
+}
+
+fn accent(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "hat" | "widehat" => "^",
+        "bar" | "overline" => "¯",
+        "vec" => "→",
+        "tilde" | "widetilde" => "~",
+        "dot" => "˙",
+        "ddot" => "¨",
+        _ => return None,
+    })
+}
+
+fn mathvariant(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "mathbf" | "textbf" | "boldsymbol" => "bold",
+        "mathit" | "textit" => "italic",
+        "mathrm" | "operatorname" => "normal",
+        "mathbb" => "double-struck",
+        "mathcal" => "script",
+        "mathfrak" => "fraktur",
+        "mathsf" => "sans-serif",
+        "mathtt" | "texttt" => "monospace",
+        _ => return None,
+    })
+}
+
+struct TexParser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl TexParser {
+    fn new(src: &str) -> Self {
+        Self {
+            chars: src.chars().collect(),
+            pos: 0,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).cloned()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek() {
+            if !c.is_whitespace() {
+                break;
+            }
+            self.pos += 1;
+        }
+    }
+
+    /// After a ``\``: a run of letters, or a single other character.
+    fn command_name(&mut self) -> String {
+        let mut name = String::new();
+        while let Some(c) = self.peek() {
+            if !c.is_ascii_alphabetic() {
+                break;
+            }
+            name.push(c);
+            self.pos += 1;
+        }
+        if name.is_empty() {
+            if let Some(c) = self.peek() {
+                name.push(c);
+                self.pos += 1;
+            }
+        }
+        name
+    }
+
+    /// The raw text inside ``{...}``, for commands like ``\text`` whose argument isn't math.
+    fn raw_group(&mut self) -> String {
+        self.skip_whitespace();
+        if self.peek() != Some('{') {
+            return self
+                .peek()
+                .map(|c| {
+                    self.pos += 1;
+                    c.to_string()
+                })
+                .unwrap_or_default();
+        }
+        self.pos += 1;
+        let mut depth = 1;
+        let mut text = String::new();
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            match c {
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                _ => {}
+            }
+            text.push(c);
+        }
+        text
+    }
+
+    /// Everything up to a closing ``}`` (or the end), as one ``<mrow>``.
+    fn row(&mut self, until: Option<char>) -> String {
+        let mut items: Vec<String> = Vec::new();
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                None => break,
+                Some(c) if Some(c) == until => {
+                    self.pos += 1;
+                    break;
+                }
+                Some('^') | Some('_') => {
+                    let base = items.pop().unwrap_or_else(|| "<mrow></mrow>".to_owned());
+                    items.push(self.scripts(base));
+                }
+                Some(_) => {
+                    if let Some(atom) = self.atom() {
+                        items.push(atom);
+                    }
+                }
+            }
+        }
+        if items.len() == 1 {
+            items.pop().unwrap()
+        } else {
+            format!("<mrow>{}</mrow>", items.concat())
+        }
+    }
+
+    /// ``base^{sup}_{sub}`` in either order.
+    fn scripts(&mut self, base: String) -> String {
+        let mut sub = None;
+        let mut sup = None;
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some('^') if sup.is_none() => {
+                    self.pos += 1;
+                    sup = Some(self.argument());
+                }
+                Some('_') if sub.is_none() => {
+                    self.pos += 1;
+                    sub = Some(self.argument());
+                }
+                _ => break,
+            }
+        }
+        match (sub, sup) {
+            (Some(sub), Some(sup)) => format!("<msubsup>{}{}{}</msubsup>", base, sub, sup),
+            (Some(sub), None) => format!("<msub>{}{}</msub>", base, sub),
+            (None, Some(sup)) => format!("<msup>{}{}</msup>", base, sup),
+            (None, None) => base,
+        }
+    }
+
+    /// A single-token or ``{grouped}`` argument.
+    fn argument(&mut self) -> String {
+        self.skip_whitespace();
+        if self.peek() == Some('{') {
+            self.pos += 1;
+            self.row(Some('}'))
+        } else {
+            self.atom().unwrap_or_else(|| "<mrow></mrow>".to_owned())
+        }
+    }
+
+    fn atom(&mut self) -> Option<String> {
+        let c = self.peek()?;
+        self.pos += 1;
+        Some(match c {
+            '{' => self.row(Some('}')),
+            '\\' => self.command(),
+            '0'..='9' | '.' => {
+                let mut number = c.to_string();
+                while let Some(d) = self.peek() {
+                    if !(d.is_ascii_digit() || d == '.') {
+                        break;
+                    }
+                    number.push(d);
+                    self.pos += 1;
+                }
+                format!("<mn>{}</mn>", number)
+            }
+            c if c.is_alphabetic() => format!("<mi>{}</mi>", c),
+            '\'' => "<mo>′</mo>".to_owned(),
+            '~' => "<mspace width=\"0.25em\"/>".to_owned(),
+            '}' => return None,
+            c => format!("<mo>{}</mo>", escape(&c.to_string())),
+        })
+    }
+
+    fn command(&mut self) -> String {
+        let name = self.command_name();
+        if let Some((tag, text)) = symbol(&name) {
+            return format!("<{0}>{1}</{0}>", tag, escape(text));
+        }
+        if is_function(&name) {
+            return format!("<mi mathvariant=\"normal\">{}</mi>", name);
+        }
+        if let Some(width) = space(&name) {
+            return format!("<mspace width=\"{}\"/>", width);
+        }
+        if let Some(mark) = accent(&name) {
+            let body = self.argument();
+            return format!("<mover accent=\"true\">{}<mo>{}</mo></mover>", body, mark);
+        }
+        if let Some(variant) = mathvariant(&name) {
+            let body = self.raw_group();
+            let tag = if body.chars().all(|c| c.is_ascii_digit()) {
+                "mn"
+            } else {
+                "mi"
+            };
+            return format!(
+                "<{0} mathvariant=\"{1}\">{2}</{0}>",
+                tag,
+                variant,
+                escape(&body)
+            );
+        }
+        match name.as_str() {
+            "frac" | "dfrac" | "tfrac" => {
+                let num = self.argument();
+                let den = self.argument();
+                format!("<mfrac>{}{}</mfrac>", num, den)
+            }
+            "binom" => {
+                let n = self.argument();
+                let k = self.argument();
+                format!(
+                    "<mrow><mo>(</mo><mfrac linethickness=\"0\">{}{}</mfrac><mo>)</mo></mrow>",
+                    n, k
+                )
+            }
+            "sqrt" => {
+                self.skip_whitespace();
+                if self.peek() == Some('[') {
+                    self.pos += 1;
+                    let index = self.row(Some(']'));
+                    let body = self.argument();
+                    format!("<mroot>{}{}</mroot>", body, index)
+                } else {
+                    format!("<msqrt>{}</msqrt>", self.argument())
+                }
+            }
+            "text" | "textrm" | "mbox" => format!("<mtext>{}</mtext>", escape(&self.raw_group())),
+            // Sizing commands just wrap the delimiter that follows:
+            "left" | "right" | "big" | "Big" | "bigg" | "Bigg" | "bigl" | "bigr" | "Bigl"
+            | "Bigr" => {
+                self.skip_whitespace();
+                match self.atom() {
+                    Some(delim) if delim.starts_with("<mo>") => {
+                        delim.replacen("<mo>", "<mo stretchy=\"true\">", 1)
+                    }
+                    Some(other) => other,
+                    None => String::new(),
+                }
+            }
+            "\\" => "<mspace linebreak=\"newline\"/>".to_owned(),
+            // Unknown: show the source, so the author can see what went wrong.
+            other => format!("<mtext>\\{}</mtext>", escape(other)),
+        }
+    }
+}
+
+/// Translate one TeX expression (without delimiters) into a ``<math>`` element, so pages can show math without MathJax.
+///
+/// This covers what shows up in quiz questions: sub/superscripts, fractions, roots, greek letters, common operators,
+/// accents, ``\text`` and font commands like ``\mathbb``. Unknown commands are shown as written instead of failing.
+pub fn tex_to_mathml(tex: &str, display: bool) -> String {
+    let mut parser = TexParser::new(tex);
+    let mut body = parser.row(None);
+    // Keep going past stray closing braces:
+    while parser.pos < parser.chars.len() {
+        parser.pos += 1;
+        body.push_str(&parser.row(None));
+    }
+    let mut output = String::new();
+    write!(
+        output,
+        "<math xmlns=\"{}\" display=\"{}\"><semantics>{}<annotation encoding=\"application/x-tex\">{}</annotation></semantics></math>",
+        MATHML_NS,
+        if display { "block" } else { "inline" },
+        body,
+        escape(tex)
+    )
+    .unwrap();
+    output
+}
+
+/// Replace MathJax-style ``\(...\)``, ``\[...\]`` and ``$$...$$`` math in rendered HTML with MathML.
+/// Code (``<pre>`` and ``<code>``) is left alone, since backslashes there are usually not math.
+pub fn html_math_to_mathml(html: &str) -> String {
+    let mut output = String::with_capacity(html.len());
+    let mut rest = html;
+    while !rest.is_empty() {
+        // Copy tags through untouched, skipping over code entirely.
+        if rest.starts_with('<') {
+            let skip_to = ["pre", "code"].iter().find_map(|tag| {
+                let open = format!("<{}", tag);
+                let after = rest.as_bytes().get(open.len()).cloned();
+                if rest.starts_with(&open) && (after == Some(b'>') || after == Some(b' ')) {
+                    let close = format!("</{}>", tag);
+                    Some(
+                        rest.find(&close)
+                            .map(|i| i + close.len())
+                            .unwrap_or(rest.len()),
+                    )
+                } else {
+                    None
+                }
+            });
+            let end =
+                skip_to.unwrap_or_else(|| rest.find('>').map(|i| i + 1).unwrap_or(rest.len()));
+            output.push_str(&rest[..end]);
+            rest = &rest[end..];
+            continue;
+        }
+        let text_end = rest.find('<').unwrap_or(rest.len());
+        output.push_str(&text_math_to_mathml(&rest[..text_end]));
+        rest = &rest[text_end..];
+    }
+    output
+}
+
+/// Handle math within one run of (escaped) HTML text.
+fn text_math_to_mathml(text: &str) -> String {
+    const DELIMITERS: &[(&str, &str, bool)] = &[
+        ("\\(", "\\)", false),
+        ("\\[", "\\]", true),
+        ("$$", "$$", true),
+    ];
+    let mut output = String::new();
+    let mut rest = text;
+    loop {
+        let next = DELIMITERS
+            .iter()
+            .filter_map(|(open, close, display)| {
+                rest.find(open).map(|i| (i, *open, *close, *display))
+            })
+            .min_by_key(|(i, _, _, _)| *i);
+        let (start, open, close, display) = match next {
+            Some(found) => found,
+            None => break,
+        };
+        let inner_start = start + open.len();
+        let inner_end = match rest[inner_start..].find(close) {
+            Some(i) => inner_start + i,
+            None => break,
+        };
+        output.push_str(&rest[..start]);
+        output.push_str(&tex_to_mathml(
+            &unescape(&rest[inner_start..inner_end]),
+            display,
+        ));
+        rest = &rest[inner_end + close.len()..];
+    }
+    output.push_str(rest);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn body(tex: &str) -> String {
+        let full = tex_to_mathml(tex, false);
+        let start = full.find("<semantics>").unwrap() + "<semantics>".len();
+        let end = full.find("<annotation").unwrap();
+        full[start..end].to_owned()
+    }
+
+    #[test]
+    fn test_tex_to_mathml() {
+        assert_eq!("<msup><mi>x</mi><mn>2</mn></msup>", body("x^2"));
+        assert_eq!(
+            "<mrow><msub><mi>a</mi><mrow><mi>i</mi><mo>+</mo><mn>1</mn></mrow></msub><mo>≤</mo><mi>π</mi></mrow>",
+            body("a_{i+1} \\leq \\pi")
+        );
+        assert_eq!(
+            "<mfrac><mi>n</mi><mrow><mn>2</mn><mi mathvariant=\"normal\">log</mi><mi>n</mi></mrow></mfrac>",
+            body("\\frac{n}{2 \\log n}")
+        );
+        assert_eq!("<msqrt><mi>x</mi></msqrt>", body("\\sqrt{x}"));
+        assert_eq!(
+            "<mi mathvariant=\"double-struck\">R</mi>",
+            body("\\mathbb{R}")
+        );
+        assert_eq!("<mtext>\\weird</mtext>", body("\\weird"));
+        assert_eq!("<mo>&lt;</mo>", body("<"));
+    }
+
+    #[test]
+    fn test_html_math() {
+        let html =
+            "<p>Is \\(O(n^2)\\) &lt; $$2^n$$?</p><pre>\\(not math\\)</pre><code>\\[nope\\]</code>";
+        let converted = html_math_to_mathml(html);
+        assert!(!converted.contains("\\(O"));
+        assert!(converted.contains("display=\"inline\"><semantics><mrow><mi>O</mi>"));
+        assert!(
+            converted.contains("display=\"block\"><semantics><msup><mn>2</mn><mi>n</mi></msup>")
+        );
+        assert!(converted.contains(" &lt; <math"));
+        assert!(converted.ends_with("<pre>\\(not math\\)</pre><code>\\[nope\\]</code>"));
+    }
+}
@@ -0,0 +1,215 @@
+use crate::Error;
+use pulldown_cmark::{html, Event, LinkType, Options, Parser, Tag};
+use schemars::JsonSchema;
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+
+/// A local image referenced from a prompt or option, loaded so exporters can bundle it.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq, Eq)]
+pub struct MediaFile {
+    /// The ``src`` attribute exactly as it appears in the rendered HTML, e.g., ``img/tree.png``.
+    pub src: String,
+    /// The file name plus a hash of its contents, e.g., ``tree-1c2b3a4d.png``, so that ``a/tree.png`` and
+    /// ``b/tree.png`` stay apart in Moodle's and QTI's flat lists of files.
+    pub name: String,
+    /// e.g., ``image/png``.
+    pub mime: String,
+    /// The file contents, base64-encoded.
+    pub base64: String,
+}
+
+impl MediaFile {
+    pub fn data_uri(&self) -> String {
+        format!("data:{};base64,{}", self.mime, self.base64)
+    }
+}
+
+fn mime_type(path: &Path) -> &'static str {
+    let ext = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match ext.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "webp" => "image/webp",
+        "bmp" => "image/bmp",
+        _ => "application/octet-stream",
+    }
+}
+
+/// 32-bit FNV-1a: tiny, and unlike ``DefaultHasher`` guaranteed to give the same name on every build.
+pub(crate) fn fnv1a(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c_9dc5, |hash: u32, b| {
+        (hash ^ u32::from(*b)).wrapping_mul(0x0100_0193)
+    })
+}
+
+/// ``tree.png`` becomes ``tree-1c2b3a4d.png``; same name and contents, same result.
+fn unique_name(path: &Path, bytes: &[u8]) -> String {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    match path.extension() {
+        Some(ext) => format!("{}-{:08x}.{}", stem, fnv1a(bytes), ext.to_string_lossy()),
+        None => format!("{}-{:08x}", stem, fnv1a(bytes)),
+    }
+}
+
+/// Anything with a scheme (``https:``, ``data:``) or a protocol-relative ``//`` is left for the browser to fetch.
+fn is_local(dest: &str) -> bool {
+    !(dest.is_empty() || dest.starts_with("//") || dest.starts_with('#') || dest.contains(':'))
+}
+
+/// pulldown-cmark doesn't expose its URL escaping, so let it render the ``<img>`` and read back the ``src``.
+pub(crate) fn rendered_src(dest: &str) -> String {
+    let mut output = String::new();
+    let img = Tag::Image(LinkType::Inline, dest.into(), "".into());
+    html::push_html(
+        &mut output,
+        vec![Event::Start(img.clone()), Event::End(img)].into_iter(),
+    );
+    let start = output.find("src=\"").map(|i| i + 5).unwrap_or(0);
+    let end = output[start..]
+        .find('"')
+        .map(|i| start + i)
+        .unwrap_or_else(|| output.len());
+    output[start..end].to_owned()
+}
+
+/// 1-based line and column of a byte offset, for error messages.
+pub(crate) fn line_column(content: &str, offset: usize) -> (usize, usize) {
+    let before = &content[..offset.min(content.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.len() - before.rfind('\n').map(|i| i + 1).unwrap_or(0) + 1;
+    (line, column)
+}
+
+/// The local images ``markdown`` refers to, as written; a cheap scan for deciding what needs rebuilding.
+pub(crate) fn local_images(markdown: &str) -> Vec<String> {
+    let mut md_opt = Options::empty();
+    md_opt.insert(Options::ENABLE_TABLES);
+    md_opt.insert(Options::ENABLE_TASKLISTS);
+    Parser::new_ext(markdown, md_opt)
+        .filter_map(|event| match event {
+            Event::Start(Tag::Image(_, dest, _)) if is_local(&dest) => Some(dest.to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Make sure every local image in ``events`` exists relative to ``base`` before we start rendering,
+/// so a typo is reported where the author wrote it instead of producing a broken export.
+pub(crate) fn check_media(
+    content: &str,
+    events: &[(Event, Range<usize>)],
+    base: Option<&Path>,
+) -> Result<(), Error> {
+    let base = match base {
+        Some(b) => b,
+        None => return Ok(()),
+    };
+    for (event, range) in events {
+        if let Event::Start(Tag::Image(_, dest, _)) = event {
+            if is_local(dest) && !base.join(dest.as_ref()).is_file() {
+                let (line, column) = line_column(content, range.start);
+                return Err(Error::MissingMedia {
+                    path: dest.to_string(),
+                    line,
+                    column,
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Load every local image referenced in ``events``, relative to ``base``.
+/// Without a base directory (e.g., text from the web editor) there is nothing to load.
+pub(crate) fn collect_media(
+    events: &[Event],
+    base: Option<&Path>,
+) -> Result<Vec<MediaFile>, Error> {
+    let base = match base {
+        Some(b) => b,
+        None => return Ok(Vec::new()),
+    };
+    let mut found: Vec<MediaFile> = Vec::new();
+    for event in events {
+        let dest = match event {
+            Event::Start(Tag::Image(_, dest, _)) if is_local(dest) => dest,
+            _ => continue,
+        };
+        let src = rendered_src(dest);
+        if found.iter().any(|m| m.src == src) {
+            continue;
+        }
+        let path = base.join(dest.as_ref());
+        let bytes = fs::read(&path)?;
+        found.push(MediaFile {
+            src,
+            name: unique_name(&path, &bytes),
+            mime: mime_type(&path).to_owned(),
+            base64: base64::encode(&bytes),
+        });
+    }
+    Ok(found)
+}
+
+/// Point every ``<img src>`` that refers to one of ``media`` somewhere else, e.g., at a data URI.
+pub(crate) fn replace_srcs<F>(html: &str, media: &[MediaFile], new_src: F) -> String
+where
+    F: Fn(&MediaFile) -> String,
+{
+    let mut output = html.to_owned();
+    for m in media {
+        output = output.replace(
+            &format!("<img src=\"{}\"", m.src),
+            &format!("<img src=\"{}\"", new_src(m)),
+        );
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn test_collect_and_inline() {
+        let dir = TempDir::new("media");
+        dir.write("img/dot.png", b"not really a png");
+
+        let md = "![a dot](img/dot.png) ![remote](https://example.com/x.png)";
+        let events = Parser::new(md).collect::<Vec<_>>();
+        let media = collect_media(&events, Some(&dir)).unwrap();
+
+        let typo = "Look:\n\n  ![gone](img/dto.png)";
+        let typo_events = Parser::new(typo).into_offset_iter().collect::<Vec<_>>();
+        let err = check_media(typo, &typo_events, Some(&dir)).unwrap_err();
+        match err {
+            Error::MissingMedia { path, line, column } => {
+                assert_eq!(("img/dto.png", 3, 3), (path.as_str(), line, column))
+            }
+            other => panic!("Expected MissingMedia error, got {:?}", other),
+        }
+
+        assert_eq!(1, media.len());
+        assert_eq!("img/dot.png", media[0].src);
+        assert_eq!("dot-e4f298b9.png", media[0].name);
+        assert_eq!("image/png", media[0].mime);
+        assert_eq!("bm90IHJlYWxseSBhIHBuZw==", media[0].base64);
+
+        let mut rendered = String::new();
+        html::push_html(&mut rendered, events.into_iter());
+        let inlined = replace_srcs(&rendered, &media, MediaFile::data_uri);
+        assert!(inlined
+            .contains("<img src=\"data:image/png;base64,bm90IHJlYWxseSBhIHBuZw==\" alt=\"a dot\""));
+        assert!(inlined.contains("<img src=\"https://example.com/x.png\""));
+    }
+}
@@ -1,12 +1,13 @@
+use crate::media::{rendered_src, replace_srcs, MediaFile};
 use crate::Error;
-use crate::Question;
+use crate::{Question, Quiz};
 use xmlwriter::*;
 
 const TEXT_NODE: &str = "text";
 const QUIZ_NODE: &str = "quiz";
 const QUESTION_NODE: &str = "question";
 
-pub fn to_moodle_xml(qs: &[Question], name: &str) -> Result<String, Error> {
+pub fn to_moodle_xml(quiz: &Quiz) -> Result<String, Error> {
     let opt = Options {
         ..Options::default()
     };
@@ -29,16 +30,36 @@ pub fn to_moodle_xml(qs: &[Question], name: &str) -> Result<String, Error> {
     xml.write_declaration();
     xml.start_element(QUIZ_NODE);
 
-    xml.start_element(QUESTION_NODE);
-    xml.write_attribute("type", "category");
-    xml.start_element("category");
-
-    write_tag_str(&mut xml, "text", name);
-    xml.end_element(); // </category>
-    xml.end_element(); // </question>
+    let base_category = quiz.category();
+    write_category(&mut xml, base_category);
 
-    for (i, q) in qs.iter().enumerate() {
-        write_multichoice(&mut xml, q, name, i)?;
+    let mut index = 0;
+    for section in quiz.sections.iter() {
+        // Titled sections become sub-categories; questions are numbered across the whole quiz.
+        let category = match section.title.as_ref() {
+            Some(title) => {
+                let category = format!("{}/{}", base_category, title);
+                write_category(&mut xml, &category);
+                category
+            }
+            None => base_category.to_owned(),
+        };
+        for q in section.questions.iter() {
+            // Parts of one question share its number (3.1, 3.2, ...) or title.
+            let name = match (q.title.is_empty(), q.part) {
+                (true, Some(part)) => format!("{}/{}.{}", category, index, part.index + 1),
+                (true, None) => format!("{}/{}", category, index),
+                (false, Some(part)) => format!("{} ({}/{})", q.title, part.index + 1, part.count),
+                (false, None) => q.title.clone(),
+            };
+            write_multichoice(&mut xml, q, &name)?;
+            if q.part
+                .map(|part| part.index + 1 == part.count)
+                .unwrap_or(true)
+            {
+                index += 1;
+            }
+        }
     }
 
     // </quiz>
@@ -47,13 +68,19 @@ pub fn to_moodle_xml(qs: &[Question], name: &str) -> Result<String, Error> {
     Ok(xml.end_document())
 }
 
+/// Questions after this go into ``category``.
+fn write_category(xml: &mut XmlWriter, category: &str) {
+    xml.start_element(QUESTION_NODE);
+    xml.write_attribute("type", "category");
+    xml.start_element("category");
+
+    write_tag_str(xml, "text", category);
+    xml.end_element(); // </category>
+    xml.end_element(); // </question>
+}
+
 /// Write a single question to XML:
-fn write_multichoice(
-    xml: &mut XmlWriter,
-    question: &Question,
-    base_name: &str,
-    index: usize,
-) -> Result<(), Error> {
+fn write_multichoice(xml: &mut XmlWriter, question: &Question, name: &str) -> Result<(), Error> {
     let num_correct = question.options.iter().filter(|q| q.correct).count();
     let num_options = question.options.len();
     // Don't write questions that don't have options!
@@ -73,9 +100,8 @@ fn write_multichoice(
     xml.start_element("question");
     xml.write_attribute("type", "multichoice");
     // <name><text>course/name/#</text></name>
-    let name = format!("{}/{}", base_name, index);
     xml.start_element("name");
-    write_tag_str(xml, TEXT_NODE, &name);
+    write_tag_str(xml, TEXT_NODE, name);
     xml.end_element(); // </name>
 
     //<questiontext format="html">
@@ -83,9 +109,20 @@ fn write_multichoice(
     //</questiontext>
     xml.start_element("questiontext");
     xml.write_attribute("format", "html");
-    write_tag_str(xml, TEXT_NODE, &question.prompt);
+    // Each part repeats the stem, so it makes sense on its own:
+    write_html_with_media(xml, &question.full_prompt(), &question.media);
     xml.end_element(); // </questiontext>
 
+    //<generalfeedback format="html">
+    //    <text>...</text>
+    //</generalfeedback>
+    if !question.explanation.is_empty() {
+        xml.start_element("generalfeedback");
+        xml.write_attribute("format", "html");
+        write_html_with_media(xml, &question.explanation, &question.media);
+        xml.end_element(); // </generalfeedback>
+    }
+
     //<defaultgrade>1.0000000</defaultgrade>
     write_tag_str(xml, "defaultgrade", "1.0");
 
@@ -104,7 +141,7 @@ fn write_multichoice(
             xml.write_attribute("fraction", INCORRECT_WEIGHT);
         }
         xml.write_attribute("format", "html");
-        write_tag_str(xml, TEXT_NODE, &ans.content);
+        write_html_with_media(xml, &ans.content, &ans.media);
 
         xml.start_element("feedback");
         xml.start_element(TEXT_NODE);
@@ -135,6 +172,23 @@ fn write_multichoice(
     Ok(())
 }
 
+/// Writes: <text>{html}</text> followed by a <file> for every image it uses, which Moodle serves at ``@@PLUGINFILE@@/{name}``.
+fn write_html_with_media(xml: &mut XmlWriter, html: &str, media: &[MediaFile]) {
+    let used = |m: &&MediaFile| html.contains(&format!("<img src=\"{}\"", m.src));
+    let rewritten = replace_srcs(html, media, |m| {
+        format!("@@PLUGINFILE@@/{}", rendered_src(&m.name))
+    });
+    write_tag_str(xml, TEXT_NODE, &rewritten);
+    for m in media.iter().filter(used) {
+        xml.start_element("file");
+        xml.write_attribute("name", &m.name);
+        xml.write_attribute("path", "/");
+        xml.write_attribute("encoding", "base64");
+        xml.write_text(&m.base64);
+        xml.end_element();
+    }
+}
+
 /// Writes: <{tag}>{str}</{tag}>
 fn write_tag_str(xml: &mut XmlWriter, tag: &str, contents: &str) {
     xml.start_element(tag);
@@ -145,6 +199,7 @@ fn write_tag_str(xml: &mut XmlWriter, tag: &str, contents: &str) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testing::TempDir;
     use crate::*;
     #[test]
     fn encodes_correctly() {
@@ -164,9 +219,100 @@ mod tests {
 - [ ] No
 - [x] Yes
         "#;
-        let qs = process_questions_str(q_src, None).unwrap();
-        let qxml = to_moodle_xml(&qs, question_bank).unwrap();
+        let quiz = process_quiz_str(q_src, question_bank, None).unwrap();
+        let qxml = to_moodle_xml(&quiz).unwrap();
         println!("{}", qxml);
         assert_eq!(qxml, expected);
     }
+
+    #[test]
+    fn explanation_is_general_feedback() {
+        let q_src = "## A?\n\n- [x] Yes\n- [ ] No\n\nBecause.\n";
+        let quiz = process_quiz_str(q_src, "ex", None).unwrap();
+        let qxml = to_moodle_xml(&quiz).unwrap();
+        assert!(qxml.contains(
+            "</questiontext><generalfeedback format=\"html\"><text>&lt;p>Because.&lt;/p>\n</text></generalfeedback>"
+        ));
+    }
+
+    #[test]
+    fn sections_become_categories() {
+        let q_src = "## A?\n\n- [x] Yes\n\n## B?\n\n- [x] Yes\n";
+        let mut quiz = process_quiz_str(q_src, "cs101/ex", None).unwrap();
+        let second = quiz.sections[0].questions.split_off(1);
+        quiz.category = Some("cs101".to_owned());
+        quiz.sections.push(Section {
+            title: Some("hard".to_owned()),
+            description: None,
+            questions: second,
+        });
+        let qxml = to_moodle_xml(&quiz).unwrap();
+        assert!(qxml.contains("<category><text>cs101</text></category>"));
+        assert!(qxml.contains("<name><text>cs101/0</text></name>"));
+        assert!(qxml.contains("<category><text>cs101/hard</text></category>"));
+        assert!(qxml.contains("<name><text>cs101/hard/1</text></name>"));
+    }
+
+    #[test]
+    fn parts_share_a_number() {
+        let q_src = "## Trees\n\nA tree has 5 nodes.\n\nHow many edges?\n\n- [x] 4\n- [ ] 5\n\nHow many roots?\n\n- [x] 1\n- [ ] 2\n\n## Next?\n\n- [x] Yes\n";
+        let quiz = process_quiz_str(q_src, "ex", None).unwrap();
+        let qxml = to_moodle_xml(&quiz).unwrap();
+        assert!(qxml.contains("<name><text>ex/0.1</text></name>"));
+        assert!(qxml.contains("<name><text>ex/0.2</text></name>"));
+        assert!(qxml.contains("<name><text>ex/1</text></name>"));
+        assert!(qxml.contains("&lt;p>A tree has 5 nodes.&lt;/p>\n&lt;p>How many roots?&lt;/p>"));
+    }
+
+    #[test]
+    fn heading_as_name() {
+        let config = Config {
+            heading_as_title: true,
+            ..Config::default()
+        };
+        let q_src = "## Snowmen\n\nDo you want to build one?\n\n- [x] Yes\n";
+        let quiz = process_quiz_str(q_src, "ex", Some(config)).unwrap();
+        let qxml = to_moodle_xml(&quiz).unwrap();
+        assert!(qxml.contains("<name><text>Snowmen</text></name><questiontext format=\"html\"><text>&lt;p>Do you want to build one?&lt;/p>\n</text>"));
+    }
+
+    #[test]
+    fn embeds_images() {
+        let dir = TempDir::new("moodle");
+        dir.write("big tree.png", b"png");
+        let input = dir.write(
+            "quiz.md",
+            b"## Which is a tree?\n\n![tree](<big tree.png>)\n\n- [x] Yes\n- [ ] No\n",
+        );
+        let quiz = process_quiz_file(input.to_str().unwrap(), Some("cs101/ex"), None).unwrap();
+
+        let qxml = to_moodle_xml(&quiz).unwrap();
+        assert!(
+            qxml.contains("&lt;img src=\"@@PLUGINFILE@@/big%20tree-6835c29c.png\" alt=\"tree\"")
+        );
+        assert!(qxml.contains(
+            "<file name=\"big tree-6835c29c.png\" path=\"/\" encoding=\"base64\">cG5n</file>"
+        ));
+    }
+
+    #[test]
+    fn same_file_name_different_images() {
+        let dir = TempDir::new("moodle-same");
+        dir.write("a/tree.png", b"first");
+        dir.write("b/tree.png", b"second");
+        let input = dir.write(
+            "quiz.md",
+            b"## Same tree?\n\n![A](a/tree.png) ![B](b/tree.png)\n\n- [x] No\n- [ ] Yes\n",
+        );
+        let quiz = process_quiz_file(input.to_str().unwrap(), Some("ex"), None).unwrap();
+
+        let media = &quiz.questions().next().unwrap().media;
+        assert_eq!(2, media.len());
+        assert_ne!(media[0].name, media[1].name);
+        let qxml = to_moodle_xml(&quiz).unwrap();
+        for m in media {
+            assert!(qxml.contains(&format!("@@PLUGINFILE@@/{}\"", m.name)));
+            assert!(qxml.contains(&format!("<file name=\"{}\"", m.name)));
+        }
+    }
 }
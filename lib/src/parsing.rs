@@ -1,49 +1,385 @@
+use crate::media::{collect_media, line_column};
 use crate::render::SyntaxHighlighter;
-use crate::{Error, QOption, Question};
-use pulldown_cmark::{Event, Parser, Tag};
+use crate::{Error, Part, Position, QOption, Question};
+use pulldown_cmark::{CowStr, Event, Tag};
+use std::path::Path;
 use std::{fmt::Write, ops::Range};
 
+/// How markdown is cut into questions; part of ``Config``.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct SplitOptions {
+    /// Only headings of this level (e.g., 2 for ``##``) start questions: headings with fewer ``#`` start a titled
+    /// section, and those with more are part of the question. By default, every heading starts a question.
+    pub question_level: Option<u32>,
+    /// A ``---`` line ends a question too, so the next one doesn't need a heading.
+    pub rules: bool,
+    /// What to do with markdown before the first heading (or ``---``).
+    pub preamble: Preamble,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Preamble {
+    /// Treat it as a question without a heading.
+    Question,
+    /// Leave it out of the quiz.
+    Ignore,
+    /// Render it as the quiz's ``description``.
+    Description,
+}
+
+// ``#[derive(Default)]`` on enums needs a newer compiler than we support.
+#[allow(clippy::derivable_impls)]
+impl Default for Preamble {
+    fn default() -> Self {
+        Preamble::Question
+    }
+}
+
+/// What ``QParser::parse_next`` found.
+#[derive(Debug)]
+pub(crate) enum Chunk<'md> {
+    /// A heading above ``SplitOptions::question_level``, as plain text.
+    Section(String),
+    /// Markdown before the first question, unless it is ``Preamble::Question``.
+    Preamble(Vec<Event<'md>>),
+    /// Markdown without options between a section heading and its first question.
+    Intro(Vec<Event<'md>>),
+    Question(HeadingChunk<'md>),
+}
+
 #[derive(Debug)]
 pub(crate) struct HeadingChunk<'md> {
     /// How many "#" were found.
     level: Option<u32>,
     /// The markdown after the "##" bit.
     header: Vec<Event<'md>>,
-    /// Any markdown before the terminating task-list:
-    contents: Vec<Event<'md>>,
-    /// The parsed-out task-list:
+    /// Markdown shared by every part of a multi-part question, before the first part's prompt:
+    stem: Vec<Event<'md>>,
+    /// Where the heading and stem are in the markdown source.
+    stem_source: Range<usize>,
+    /// Where the markdown after the heading starts.
+    heading_end: usize,
+    /// One per task-list; usually just one.
+    parts: Vec<ChunkPart<'md>>,
+    /// Any markdown after the last task-list:
+    explanation: Vec<Event<'md>>,
+    explanation_source: Range<usize>,
+}
+
+/// A prompt and the task-list answering it.
+#[derive(Debug)]
+struct ChunkPart<'md> {
+    /// Any markdown between the stem (or the previous task-list) and this one:
+    prompt: Vec<Event<'md>>,
+    source: Range<usize>,
     options: TaskList<'md>,
 }
 
 impl<'md> HeadingChunk<'md> {
-    pub(crate) fn finish(self, renderer: &SyntaxHighlighter) -> Result<Question, Error> {
-        let mut prompt = String::new();
-        if let Some(lvl) = self.level {
-            write!(prompt, "<h{}>", lvl).unwrap();
-        } else {
-            prompt.push_str("<b><i>");
+    /// ``markdown`` is the text this chunk was parsed from.
+    /// A chunk with several task-lists becomes several questions, one per part, that share a stem.
+    /// With ``heading_as_title``, the heading becomes each question's ``title`` instead of starting its prompt.
+    /// A ``{reorder}`` at the end of the heading, stem or a prompt marks the question(s) as ``reorderable``; ``{tag=...}``
+    /// and ``{difficulty=...}`` there set ``tags`` and ``difficulty`` the same way.
+    pub(crate) fn finish(
+        mut self,
+        renderer: &SyntaxHighlighter,
+        markdown: &str,
+        base: Option<&Path>,
+        heading_as_title: bool,
+    ) -> Result<Vec<Question>, Error> {
+        // Markers to leave out of the ``_markdown`` fields, too:
+        let mut cuts = Vec::new();
+        let mut shared = Annotations::default();
+        shared.strip(
+            &mut self.header,
+            markdown,
+            self.stem_source.start..self.heading_end,
+            &mut cuts,
+        );
+        shared.strip(
+            &mut self.stem,
+            markdown,
+            self.heading_end..self.stem_source.end,
+            &mut cuts,
+        );
+        let mut title = String::new();
+        let mut stem = String::new();
+        let mut stem_media = Vec::new();
+        let mut stem_start = self.stem_source.start;
+        if heading_as_title {
+            title = plain_text(&self.header);
+            stem_start = self.heading_end;
+        } else if let Some(lvl) = self.level {
+            stem_media = collect_media(&self.header, base)?;
+            write!(stem, "<h{}>", lvl).unwrap();
+            renderer.render(&mut stem, &self.header)?;
+            write!(stem, "</h{}>", lvl).unwrap();
         }
-        renderer.render(&mut prompt, &self.header)?;
-        if let Some(lvl) = self.level {
-            write!(prompt, "</h{}>", lvl).unwrap();
-        } else {
-            prompt.push_str("</i></b>");
+        stem_media.extend(collect_media(&self.stem, base)?);
+        renderer.render(&mut stem, &self.stem)?;
+        let mut explanation = String::new();
+        renderer.render(&mut explanation, &self.explanation)?;
+        let explanation_media = collect_media(&self.explanation, base)?;
+
+        let count = self.parts.len();
+        let mut questions = Vec::with_capacity(count);
+        for (index, mut part) in self.parts.into_iter().enumerate() {
+            let mut annotations = shared.clone();
+            annotations.strip(&mut part.prompt, markdown, part.source.clone(), &mut cuts);
+            let mut media = stem_media.clone();
+            media.extend(collect_media(&part.prompt, base)?);
+            let mut prompt = String::new();
+            renderer.render(&mut prompt, &part.prompt)?;
+            let ordered = part.options.ordered;
+            let options = part
+                .options
+                .question_options
+                .into_iter()
+                .map(|it| it.finish(renderer, markdown, base))
+                .collect::<Result<Vec<_>, _>>()?;
+            let mut question = Question {
+                prompt,
+                prompt_markdown: source_text_without(markdown, part.source.clone(), &cuts),
+                ordered,
+                reorderable: annotations.reorder,
+                tags: annotations.tags,
+                difficulty: annotations.difficulty,
+                options,
+                explanation: String::new(),
+                explanation_markdown: String::new(),
+                stem: stem.clone(),
+                stem_markdown: source_text_without(
+                    markdown,
+                    stem_start..self.stem_source.end,
+                    &cuts,
+                ),
+                title: title.clone(),
+                part: Some(Part { index, count }),
+                media,
+                position: Some(position(markdown, part.source.start)),
+            };
+            if count == 1 {
+                // The usual case: the heading (if any) is part of the prompt.
+                question.prompt = format!("{}{}", question.stem, question.prompt);
+                question.prompt_markdown =
+                    source_text_without(markdown, stem_start..part.source.end, &cuts);
+                question.stem.clear();
+                question.stem_markdown.clear();
+                question.part = None;
+                question.position = Some(position(markdown, self.stem_source.start));
+            }
+            if index + 1 == count {
+                question.explanation = explanation.clone();
+                question.explanation_markdown =
+                    source_text(markdown, self.explanation_source.clone());
+                question.media.extend(explanation_media.iter().cloned());
+            }
+            questions.push(question);
+        }
+        Ok(questions)
+    }
+}
+
+/// ``range`` of ``markdown``, without surrounding blank lines.
+fn source_text(markdown: &str, range: Range<usize>) -> String {
+    source_text_without(markdown, range, &[])
+}
+
+/// ``range`` of ``markdown`` minus any of ``cuts`` inside it, without surrounding blank lines.
+fn source_text_without(markdown: &str, range: Range<usize>, cuts: &[Range<usize>]) -> String {
+    let mut text = String::new();
+    let mut from = range.start;
+    let mut inside: Vec<&Range<usize>> = cuts
+        .iter()
+        .filter(|cut| range.start <= cut.start && cut.end <= range.end)
+        .collect();
+    inside.sort_by_key(|cut| cut.start);
+    for cut in inside {
+        text.push_str(markdown.get(from..cut.start).unwrap_or_default());
+        from = cut.end;
+    }
+    text.push_str(markdown.get(from..range.end).unwrap_or_default());
+    text.trim().to_owned()
+}
+
+/// Where the last ``{name}`` in ``range`` of ``markdown`` is, with the spaces before it; for a marker that
+/// ``strip_question_annotation`` just found at the end of that range's text.
+fn marker_range(markdown: &str, range: Range<usize>, name: &str) -> Option<Range<usize>> {
+    let marker = format!("{{{}}}", name);
+    let at = range.start + markdown.get(range.clone())?.rfind(&marker)?;
+    let before = markdown[range.start..at].trim_end_matches(&[' ', '\t'][..]);
+    Some(range.start + before.len()..at + marker.len())
+}
+
+/// Remove a ``{name}`` (e.g., ``{pin}``) from the end of the last text in ``events``, saying whether it was there.
+fn strip_annotation(events: &mut [Event], name: &str) -> bool {
+    let marker = format!("{{{}}}", name);
+    let last = events
+        .iter_mut()
+        .rev()
+        .find(|event| !matches!(event, Event::End(_)));
+    if let Some(Event::Text(text)) = last {
+        if let Some(rest) = text.trim_end().strip_suffix(marker.as_str()) {
+            *text = CowStr::from(rest.trim_end().to_owned());
+            return true;
+        }
+    }
+    false
+}
+
+/// What ``{reorder}``, ``{tag=NAME}`` and ``{difficulty=LEVEL}`` annotations said about a question.
+#[derive(Debug, Clone, Default)]
+struct Annotations {
+    reorder: bool,
+    tags: Vec<String>,
+    difficulty: Option<String>,
+}
+
+impl Annotations {
+    /// Take every annotation off the end of ``events``, which were parsed from ``range`` of ``markdown``, and add
+    /// where each was written to ``cuts``. A later ``difficulty`` replaces an earlier one.
+    fn strip(
+        &mut self,
+        events: &mut [Event],
+        markdown: &str,
+        mut range: Range<usize>,
+        cuts: &mut Vec<Range<usize>>,
+    ) {
+        let mut found = Vec::new();
+        while let Some(annotation) = strip_question_annotation(events) {
+            if let Some(cut) = marker_range(markdown, range.clone(), &annotation) {
+                range.end = cut.start;
+                cuts.push(cut);
+            }
+            found.push(annotation);
+        }
+        for annotation in found.into_iter().rev() {
+            if annotation == "reorder" {
+                self.reorder = true;
+            } else if let Some(tag) = annotation.strip_prefix("tag=") {
+                if !self.tags.iter().any(|t| t == tag) {
+                    self.tags.push(tag.to_owned());
+                }
+            } else if let Some(level) = annotation.strip_prefix("difficulty=") {
+                self.difficulty = Some(level.to_owned());
+            }
+        }
+    }
+}
+
+/// Remove a ``{reorder}``, ``{tag=NAME}`` or ``{difficulty=LEVEL}`` from the end of the last text in ``events``, and
+/// return what was inside the braces. Names and levels are letters, digits and ``-``; anything else stays as text.
+fn strip_question_annotation(events: &mut [Event]) -> Option<String> {
+    let last = events
+        .iter_mut()
+        .rev()
+        .find(|event| !matches!(event, Event::End(_)));
+    let text = match last {
+        Some(Event::Text(text)) => text,
+        _ => return None,
+    };
+    let trimmed = text.trim_end().strip_suffix('}')?;
+    let open = trimmed.rfind('{')?;
+    let inside = &trimmed[open + 1..];
+    let value = inside
+        .strip_prefix("tag=")
+        .or_else(|| inside.strip_prefix("difficulty="));
+    let known = match value {
+        Some(value) => !value.is_empty() && value.chars().all(|c| c.is_alphanumeric() || c == '-'),
+        None => inside == "reorder",
+    };
+    if !known {
+        return None;
+    }
+    let inside = inside.to_owned();
+    let rest = trimmed[..open].trim_end().to_owned();
+    *text = CowStr::from(rest);
+    Some(inside)
+}
+
+fn position(markdown: &str, offset: usize) -> Position {
+    let (line, column) = line_column(markdown, offset);
+    Position { line, column }
+}
+
+/// The text of a heading, without markup.
+fn plain_text(events: &[Event]) -> String {
+    let mut text = String::new();
+    for event in events {
+        match event {
+            Event::Text(t) | Event::Code(t) => text.push_str(t),
+            Event::SoftBreak | Event::HardBreak => text.push(' '),
+            _ => {}
+        }
+    }
+    text
+}
+
+/// Un-indent the lines after the first, which markdown indents to keep them inside a list item.
+fn dedent(text: &str) -> String {
+    let indent = |line: &str| line.len() - line.trim_start().len();
+    let mut lines = text.lines();
+    let first = lines.next().unwrap_or_default();
+    let rest: Vec<&str> = lines.collect();
+    let common = rest
+        .iter()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| indent(line))
+        .min()
+        .unwrap_or(0);
+    let mut output = first.to_owned();
+    for line in rest {
+        output.push('\n');
+        output.push_str(line.get(common..).unwrap_or_default());
+    }
+    output
+}
+
+/// A short description of ``event`` for error messages, e.g., ``text "Yes"``.
+fn describe(event: &Event) -> String {
+    let tag_name = |tag: &Tag| match tag {
+        Tag::Paragraph => "a paragraph".to_owned(),
+        Tag::Heading(level, _, _) => format!("an {} heading", level),
+        Tag::List(_) => "a list".to_owned(),
+        Tag::Item => "a list item".to_owned(),
+        Tag::CodeBlock(_) => "a code block".to_owned(),
+        Tag::BlockQuote => "a block quote".to_owned(),
+        other => format!("{:?}", other),
+    };
+    match event {
+        Event::Text(text) => format!("text {:?}", text.as_ref()),
+        Event::Code(code) => format!("code {:?}", code.as_ref()),
+        Event::Html(html) => format!("HTML {:?}", html.as_ref()),
+        Event::Start(tag) => format!("the start of {}", tag_name(tag)),
+        Event::End(tag) => format!("the end of {}", tag_name(tag)),
+        Event::TaskListMarker(_) => "a checkbox".to_owned(),
+        other => format!("{:?}", other),
+    }
+}
+
+/// Where the last top-level block (paragraph, code block, ...) in ``events`` starts.
+fn last_block_start(events: &[Event]) -> usize {
+    let mut depth = 0;
+    let mut last = 0;
+    for (i, event) in events.iter().enumerate() {
+        match event {
+            Event::Start(_) => {
+                if depth == 0 {
+                    last = i;
+                }
+                depth += 1;
+            }
+            Event::End(_) => depth -= 1,
+            _ => {
+                if depth == 0 {
+                    last = i;
+                }
+            }
         }
-        renderer.render(&mut prompt, &self.contents)?;
-        let ordered = self.options.ordered;
-        let options = self
-            .options
-            .question_options
-            .into_iter()
-            .map(|it| it.finish(renderer))
-            .collect::<Result<Vec<_>, _>>()?;
-
-        Ok(Question {
-            prompt,
-            ordered,
-            options,
-        })
     }
+    last
 }
 
 #[derive(Debug)]
@@ -57,38 +393,72 @@ struct TaskList<'md> {
 struct TaskListOption<'md> {
     correct: bool,
     contents: Vec<Event<'md>>,
+    /// Where the contents (after the ``[x]``) are in the markdown source.
+    source: Range<usize>,
+    /// Where the ``[x]`` is.
+    checkbox: usize,
 }
 impl<'md> TaskListOption<'md> {
-    fn finish(self, renderer: &SyntaxHighlighter) -> Result<QOption, Error> {
+    /// A ``{pin}`` at the end of an option keeps it in place when ``balance`` reorders the rest.
+    fn finish(
+        mut self,
+        renderer: &SyntaxHighlighter,
+        markdown: &str,
+        base: Option<&Path>,
+    ) -> Result<QOption, Error> {
+        let pinned = strip_annotation(&mut self.contents, "pin");
+        let cuts: Vec<Range<usize>> = if pinned {
+            marker_range(markdown, self.source.clone(), "pin")
+                .into_iter()
+                .collect()
+        } else {
+            Vec::new()
+        };
         let mut content = String::new();
         renderer.render(&mut content, &self.contents)?;
         Ok(QOption {
             correct: self.correct,
+            pinned,
             content,
+            content_markdown: dedent(&source_text_without(markdown, self.source, &cuts)),
+            media: collect_media(&self.contents, base)?,
+            position: Some(position(markdown, self.checkbox)),
         })
     }
 }
 
 pub(crate) struct QParser<'md> {
+    /// The author's text, for error positions.
+    markdown: &'md str,
+    split: SplitOptions,
     tokens: Vec<Event<'md>>,
     spans: Vec<Range<usize>>,
     position: usize,
     list_stack: Vec<usize>,
+    /// The last chunk was a section heading, so text without options introduces that section.
+    after_section: bool,
 }
 
 impl<'md> QParser<'md> {
-    pub(crate) fn new(parser: Parser<'md>) -> Self {
+    pub(crate) fn new(
+        markdown: &'md str,
+        events: Vec<(Event<'md>, Range<usize>)>,
+        split: SplitOptions,
+    ) -> Self {
         let mut tokens = Vec::new();
         let mut spans = Vec::new();
-        for (tok, span) in parser.into_offset_iter() {
+        for (tok, span) in events {
             tokens.push(tok);
             spans.push(span);
         }
         Self {
+            markdown,
+            split,
             tokens,
             spans,
             position: 0,
             list_stack: Vec::new(),
+            after_section: false,
         }
     }
     fn peek(&self) -> Option<Event<'md>> {
@@ -102,30 +472,100 @@ impl<'md> QParser<'md> {
         out.cloned()
     }
 
-    pub(crate) fn parse_next(&mut self) -> Result<Option<HeadingChunk<'md>>, Error> {
-        let mut header = Vec::new();
-        let mut contents = Vec::new();
+    /// An ``UnexpectedMarkdown`` error for the event at ``index`` (or the end of the file).
+    fn unexpected(&self, index: usize, expected: &str) -> Error {
+        let (found, offset) = match (self.tokens.get(index), self.spans.get(index)) {
+            (Some(event), Some(span)) => (describe(event), span.start),
+            _ => ("the end of the file".to_owned(), self.markdown.len()),
+        };
+        let (line, column) = line_column(self.markdown, offset);
+        Error::UnexpectedMarkdown {
+            expected: expected.to_owned(),
+            found,
+            line,
+            column,
+        }
+    }
+
+    /// Whether ``event`` ends one chunk and starts the next: a heading at or above the question level, or a ``---``
+    /// outside of any list if those split questions too.
+    fn is_boundary(&self, event: &Event) -> bool {
+        match event {
+            Event::Start(Tag::Heading(lvl, _, _)) => match self.split.question_level {
+                Some(level) => *lvl as u32 <= level,
+                None => true,
+            },
+            Event::Rule => self.split.rules && self.list_stack.is_empty(),
+            _ => false,
+        }
+    }
+
+    /// Everything up to the next boundary, if it has no checkboxes outside of a nested list.
+    fn peek_intro(&self) -> Option<Vec<Event<'md>>> {
+        let mut intro = Vec::new();
+        let mut lists = 0;
+        for event in self.tokens[self.position..].iter() {
+            match event {
+                Event::Start(Tag::List(_)) => lists += 1,
+                Event::End(Tag::List(_)) => lists -= 1,
+                Event::TaskListMarker(_) if lists == 1 => return None,
+                _ if lists == 0 && self.is_boundary(event) => break,
+                _ => {}
+            }
+            intro.push(event.clone());
+        }
+        Some(intro)
+    }
 
-        let here = self.get();
-        if here.is_none() {
-            return Ok(None);
+    pub(crate) fn parse_next(&mut self) -> Result<Option<Chunk<'md>>, Error> {
+        let after_section = std::mem::replace(&mut self.after_section, false);
+        let mut header = Vec::new();
+        let at_start = self.position == 0;
+        // Skip the ``---`` that ended the last question:
+        while self.split.rules && self.peek() == Some(Event::Rule) {
+            self.position += 1;
         }
-        let here = here.unwrap();
+
+        let first = self.position;
+        let source_start = match self.spans.get(self.position) {
+            Some(span) => span.start,
+            None => return Ok(None),
+        };
+        let here = self.get().unwrap();
         let mut level: Option<u32> = None;
 
         match here {
-            Event::Start(Tag::Heading(lvl)) => {
-                level = Some(lvl);
+            Event::Start(Tag::Heading(lvl, _, _)) if self.is_boundary(&here) => {
+                level = Some(lvl as u32);
             }
             _ => {}
         }
+        if at_start && level.is_none() && self.split.preamble != Preamble::Question {
+            let mut preamble = vec![here];
+            while let Some(next) = self.peek() {
+                if self.is_boundary(&next) {
+                    break;
+                }
+                preamble.push(next);
+                self.position += 1;
+            }
+            return Ok(Some(Chunk::Preamble(preamble)));
+        }
+        if after_section && level.is_none() {
+            self.position -= 1;
+            if let Some(intro) = self.peek_intro() {
+                self.position += intro.len();
+                return Ok(Some(Chunk::Intro(intro)));
+            }
+            self.position += 1;
+        }
         // Process insides until end-heading of this level:
         if let Some(h) = level {
             loop {
                 if let Some(next) = self.get() {
                     match &next {
-                        Event::End(Tag::Heading(closed)) => {
-                            debug_assert_eq!(*closed, h);
+                        Event::End(Tag::Heading(closed, _, _)) => {
+                            debug_assert_eq!(*closed as u32, h);
                             break;
                         }
                         _ => {}
@@ -139,6 +579,10 @@ impl<'md> QParser<'md> {
                     )));
                 }
             }
+            if self.split.question_level.map(|q| h < q).unwrap_or(false) {
+                self.after_section = true;
+                return Ok(Some(Chunk::Section(plain_text(&header))));
+            }
         } else {
             // otherwise, special "no-header" chunk.
             // un-get here
@@ -146,48 +590,64 @@ impl<'md> QParser<'md> {
         }
 
         // Now, read the question body.
-        // We expect to find 1 and only one "task_list".
+        // We expect to find one "task_list" per part; usually just one.
         // We're done when:
         //  - we find the next header event.
         //  - we find an EOF.
         let start = self.position;
-        let mut task_list_start: Option<usize> = None;
-        let mut task_list_end: Option<usize> = None;
+        // (start, end) of each top-level list with checkboxes:
+        let mut task_lists: Vec<(usize, Option<usize>)> = Vec::new();
+        let mut in_code_block = false;
         loop {
             if let Some(next) = self.get() {
+                // The renderer expects nothing but text inside code blocks:
+                if in_code_block {
+                    match &next {
+                        Event::Text(_) => {}
+                        Event::End(Tag::CodeBlock(_)) => in_code_block = false,
+                        _ => return Err(self.unexpected(self.position - 1, "code")),
+                    }
+                    continue;
+                }
+                if self.is_boundary(&next) {
+                    // unget it for the next chunk.
+                    self.position -= 1;
+                    // stop looping.
+                    break;
+                }
                 match &next {
                     Event::End(Tag::List(_)) => {
-                        let closed_list = self.list_stack.pop().unwrap();
-                        if let Some(opened_list) = task_list_start {
-                            if opened_list == closed_list {
-                                task_list_end = Some(self.position);
+                        let closed_list = match self.list_stack.pop() {
+                            Some(list) => list,
+                            None => return Err(self.unexpected(self.position - 1, "text")),
+                        };
+                        if let Some((opened_list, list_end)) = task_lists.last_mut() {
+                            if *opened_list == closed_list {
+                                *list_end = Some(self.position);
                             }
                         }
                     }
                     Event::Start(tag) => match tag {
-                        Tag::Heading(_) => {
-                            // unget it for the next chunk.
-                            self.position -= 1;
-                            // stop looping.
-                            break;
-                        }
                         Tag::List(_) => {
                             // Remember this list!
                             self.list_stack.push(self.position - 1);
                         }
+                        Tag::CodeBlock(_) => in_code_block = true,
                         _ => {}
                     },
                     Event::TaskListMarker(_) => {
                         if self.list_stack.len() > 1 {
-                            return Err(Error::NestedTaskList);
-                        } else if self.list_stack.len() == 0 {
+                            // A checkbox inside an option's own bullet points: which question is it for?
+                            let offset = self.spans[self.position - 1].start;
+                            let (line, column) = line_column(self.markdown, offset);
+                            return Err(Error::NestedTaskList { line, column });
+                        } else if self.list_stack.is_empty() {
                             // This is probably impossible.
                             return Err(Error::TaskListWithoutList);
                         }
-                        if let Some(prev) = task_list_start.replace(self.list_stack[0]) {
-                            if prev != self.list_stack[0] {
-                                return Err(Error::TooManyTaskLists);
-                            }
+                        let outer = self.list_stack[0];
+                        if task_lists.last().map(|(list, _)| *list) != Some(outer) {
+                            task_lists.push((outer, None));
                         }
                     }
                     _ => {}
@@ -200,51 +660,108 @@ impl<'md> QParser<'md> {
 
         let end = self.position;
         // start..end is the question
-        // task_list_start .. task_list_end is the options.
-
-        let options = match (task_list_start, task_list_end) {
-            (None, None) | (None, _) | (_, None) => {
-                return Err(Error::NoOptionsFound);
-            }
-            (Some(t_start), Some(t_end)) => {
-                if t_end != end {
-                    return Err(Error::ContentIgnored);
-                }
-                contents.extend(self.tokens[start..t_start].iter().cloned());
-                self.position = t_start;
-                self.parse_task_list()?
+        // each task_list start..end is a part's options; anything before it is that part's prompt.
+        // the last task_list end..end is the explanation.
+        if task_lists.is_empty() {
+            return Err(Error::NoOptionsFound);
+        }
+        let mut parts = Vec::with_capacity(task_lists.len());
+        let mut prompt_start = start;
+        for (t_start, t_end) in task_lists.iter().copied() {
+            let t_end = match t_end {
+                Some(t_end) => t_end,
+                None => return Err(Error::NoOptionsFound),
+            };
+            if t_start < first {
+                // A heading inside a list item split these options from their question.
+                return Err(self.unexpected(first, "the options to end before a heading"));
             }
-        };
+            self.position = t_start;
+            parts.push(ChunkPart {
+                prompt: self.tokens[prompt_start..t_start].to_vec(),
+                source: self.source_range(prompt_start, t_start),
+                options: self.parse_task_list()?,
+            });
+            prompt_start = t_end;
+        }
+        self.position = end;
+
+        // With several parts, only the last block before the first list is its prompt; the rest is shared.
+        let mut stem = Vec::new();
+        let mut stem_end = start;
+        if parts.len() > 1 {
+            let first_part = &mut parts[0];
+            let split = last_block_start(&first_part.prompt);
+            stem = first_part.prompt.drain(..split).collect();
+            stem_end += split;
+            first_part.source = self.source_range(stem_end, task_lists[0].0);
+        }
 
-        Ok(Some(HeadingChunk {
+        Ok(Some(Chunk::Question(HeadingChunk {
             level,
             header,
-            contents,
-            options,
-        }))
+            stem,
+            stem_source: source_start..self.source_range(stem_end, stem_end).start,
+            heading_end: self.source_range(start, start).start,
+            parts,
+            explanation: self.tokens[prompt_start..end].to_vec(),
+            explanation_source: self.source_range(prompt_start, end),
+        })))
+    }
+
+    /// The markdown source of ``tokens[from..to]``.
+    fn source_range(&self, from: usize, to: usize) -> Range<usize> {
+        let offset = |i: usize| {
+            self.spans
+                .get(i)
+                .map(|span| span.start)
+                .unwrap_or_else(|| self.markdown.len())
+        };
+        if from < to {
+            offset(from)..self.spans[to - 1].end
+        } else {
+            offset(from)..offset(from)
+        }
     }
 
     fn parse_task_list_option(&mut self) -> Result<TaskListOption<'md>, Error> {
         let mut contents = Vec::new();
 
+        let item = self.position;
         match self.get() {
             Some(Event::Start(Tag::Item)) => {}
-            x => panic!("expected list-item start, found: {:?}", x),
+            _ => return Err(self.unexpected(item, "an option")),
         };
         let correct = match self.get() {
             Some(Event::TaskListMarker(val)) => val,
-            x => panic!("expected [_], found: {:?}", x),
+            _ => return Err(self.unexpected(item + 1, "a [ ] or [x] checkbox")),
         };
+        let checkbox = self.spans[self.position - 1].start;
+        let source = self.spans[self.position - 1].end..self.spans[item].end;
 
+        // Stop at the end of this item, not one inside a nested list:
+        let mut depth = 0;
         loop {
             match self.get() {
-                Some(Event::End(Tag::Item)) => break,
-                Some(x) => contents.push(x),
-                None => return Err(Error::Internal),
+                Some(Event::End(Tag::Item)) if depth == 0 => break,
+                Some(x) => {
+                    match x {
+                        Event::Start(Tag::Item) => depth += 1,
+                        Event::End(Tag::Item) => depth -= 1,
+                        _ => {}
+                    }
+                    contents.push(x)
+                }
+                None => return Err(self.unexpected(self.position, "the end of the option")),
             };
         }
 
-        Ok(TaskListOption { correct, contents })
+        Ok(TaskListOption {
+            correct,
+            contents,
+            source,
+            checkbox,
+        })
     }
 
     fn parse_task_list(&mut self) -> Result<TaskList<'md>, Error> {
@@ -266,7 +783,11 @@ impl<'md> QParser<'md> {
                     question_options.push(self.parse_task_list_option()?)
                 }
                 None => break,
-                other => panic!("Expected end-of-list or start-item; found {:?}", other),
+                Some(_) => {
+                    return Err(
+                        self.unexpected(self.position, "another option or the end of the list")
+                    )
+                }
             }
         }
 
@@ -276,3 +797,131 @@ impl<'md> QParser<'md> {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{Compiler, Config, Error, Preamble, SplitOptions};
+
+    #[test]
+    fn test_missing_checkbox() {
+        let compiler = Compiler::new(Config::default()).unwrap();
+        let err = compiler
+            .process_str("## Pick one\n\n- [x] Yes\n- No\n")
+            .unwrap_err();
+        match err {
+            Error::UnexpectedMarkdown {
+                expected,
+                found,
+                line,
+                column,
+            } => {
+                assert_eq!("a [ ] or [x] checkbox", expected);
+                assert_eq!("text \"No\"", found);
+                assert_eq!((4, 3), (line, column));
+            }
+            other => panic!("Expected UnexpectedMarkdown, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_rich_options() {
+        let compiler = Compiler::new(Config::default()).unwrap();
+        let qs = compiler
+            .process_str(
+                "## Which?\n\n- [x] Because:\n  - it is nested\n  - and plain\n\n  A second paragraph.\n- [ ] Code:\n\n  ```\n  x = 1\n  ```\n",
+            )
+            .unwrap();
+        let options = &qs[0].options;
+        assert_eq!(2, options.len());
+        assert_eq!(
+            "<p>Because:</p>\n<ul>\n<li>it is nested</li>\n<li>and plain</li>\n</ul>\n<p>A second paragraph.</p>\n",
+            options[0].content
+        );
+        assert_eq!(
+            "Because:\n- it is nested\n- and plain\n\nA second paragraph.",
+            options[0].content_markdown
+        );
+        assert!(options[1].content.contains("<pre"));
+        assert_eq!("Code:\n\n```\nx = 1\n```", options[1].content_markdown);
+    }
+
+    #[test]
+    fn test_nested_task_list() {
+        let compiler = Compiler::new(Config::default()).unwrap();
+        let err = compiler
+            .process_str("## Pick one\n\n- [x] Yes\n  - [ ] Maybe\n- [ ] No\n")
+            .unwrap_err();
+        match err {
+            Error::NestedTaskList { line, column } => assert_eq!((4, 5), (line, column)),
+            other => panic!("Expected NestedTaskList, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_tags_and_difficulty() {
+        let compiler = Compiler::new(Config::default()).unwrap();
+        let qs = compiler
+            .process_str(
+                "## Trees {tag=graphs} {reorder}\n\nA tree has 5 nodes. {difficulty=easy}\n\nHow many edges? {tag=counting} {difficulty=hard}\n\n- [x] 4\n- [ ] 5\n\nHow many roots?\n\n- [x] 1\n- [ ] 2\n\n## Not ours {tag=a_b}\n\n- [x] x\n",
+            )
+            .unwrap();
+        assert_eq!(vec!["graphs", "counting"], qs[0].tags);
+        assert_eq!(Some("hard"), qs[0].difficulty.as_deref());
+        assert_eq!(vec!["graphs"], qs[1].tags);
+        assert_eq!(Some("easy"), qs[1].difficulty.as_deref());
+        assert!(qs[0].reorderable && qs[1].reorderable);
+        assert_eq!("## Trees\n\nA tree has 5 nodes.", qs[0].stem_markdown);
+        assert_eq!("How many edges?", qs[0].prompt_markdown);
+        assert!(!qs[0].stem.contains('{') && !qs[0].prompt.contains('{'));
+        // Only letters, digits and ``-``:
+        assert!(qs[2].tags.is_empty());
+        assert!(qs[2].prompt_markdown.ends_with("{tag=a_b}"));
+    }
+
+    /// A cheap stand-in for the fuzz target in ``lib/fuzz``: every short mix of awkward markdown should give
+    /// questions or an error, never a panic.
+    #[test]
+    fn test_never_panics() {
+        let fragments = [
+            "## Q?\n",
+            "# H\n",
+            "- [x] a\n",
+            "- [ ] b\n",
+            "1. [x] c\n",
+            "- d\n",
+            "  - [x] nested\n",
+            "  - plain nested\n",
+            "```py\nx = 1\n```\n",
+            "$x_1$ and $$\n\\alpha\n$$\n",
+            "> - [x] quoted\n",
+            "text\n",
+            "\n",
+            "- # heading in a list\n",
+            "<b>html</b>\n",
+            "|a|b|\n|-|-|\n|1|2|\n",
+            "![remote](http://example.com/y.png)\n",
+            "---\n",
+        ];
+        let split = SplitOptions {
+            question_level: Some(2),
+            rules: true,
+            preamble: Preamble::Description,
+        };
+        for config in [
+            Config::default(),
+            Config {
+                split,
+                ..Config::default()
+            },
+        ] {
+            let compiler = Compiler::new(config).unwrap();
+            for a in fragments.iter() {
+                for b in fragments.iter() {
+                    for c in fragments.iter() {
+                        let _ = compiler.process_str(&format!("{}{}{}", a, b, c));
+                    }
+                }
+            }
+        }
+    }
+}
@@ -1,17 +1,33 @@
+use crate::diagram;
+use crate::media::line_column;
 use crate::Error;
 use pulldown_cmark::{html, CodeBlockKind, CowStr, Event, Tag};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::ops::Range;
+use std::sync::Arc;
 use syntect::easy::HighlightLines;
-use syntect::highlighting::ThemeSet;
+use syntect::highlighting::{Theme, ThemeSet};
 use syntect::html::{
-    append_highlighted_html_for_styled_line, highlighted_html_for_string,
-    start_highlighted_html_snippet, IncludeBackground,
+    append_highlighted_html_for_styled_line, css_for_theme_with_class_style,
+    highlighted_html_for_string, start_highlighted_html_snippet, ClassStyle, ClassedHTMLGenerator,
+    IncludeBackground,
 };
-use syntect::parsing::SyntaxSet;
+use syntect::parsing::{SyntaxReference, SyntaxSet};
+use syntect::util::LinesWithEndings;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct SyntaxHighlightingOptions {
     pub theme: String,
     pub default_lang: String,
+    pub style: HighlightStyle,
+    /// Directories of extra ``.sublime-syntax`` files, searched recursively.
+    pub syntax_dirs: Vec<String>,
+    /// Directories of extra ``.tmTheme`` files; each theme is named after its file.
+    pub theme_dirs: Vec<String>,
+    /// Fail with ``Error::MissingSyntaxLang`` instead of showing unknown languages as plain text.
+    pub strict: bool,
 }
 
 impl Default for SyntaxHighlightingOptions {
@@ -19,49 +35,322 @@ impl Default for SyntaxHighlightingOptions {
         Self {
             default_lang: "text".to_owned(),
             theme: "InspiredGitHub".to_owned(),
+            style: HighlightStyle::Inline,
+            syntax_dirs: Vec::new(),
+            theme_dirs: Vec::new(),
+            strict: false,
         }
     }
 }
 
+/// How highlighted code carries its colors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HighlightStyle {
+    /// ``style=`` attributes on every token; works anywhere, but is bulky and fixed to one theme.
+    Inline,
+    /// ``qd-``-prefixed class names; pair with ``SyntaxHighlightingOptions::stylesheet``.
+    Classes,
+}
+
+lazy_static! {
+    /// The bundled syntaxes and themes, shared by every highlighter that doesn't add its own.
+    pub(crate) static ref DEFAULT_SYNTAXES: Arc<SyntaxSet> = Arc::new(SyntaxSet::load_defaults_newlines());
+    pub(crate) static ref DEFAULT_THEMES: Arc<ThemeSet> = Arc::new(ThemeSet::load_defaults());
+}
+
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "qd-" };
+
 impl SyntaxHighlightingOptions {
     pub(crate) fn create(&self) -> Result<SyntaxHighlighter, Error> {
-        let ts = ThemeSet::load_defaults();
+        let ts = self.theme_set()?;
         if ts.themes.get(&self.theme).is_none() {
             return Err(Error::MissingSyntaxTheme(self.theme.clone()));
         }
         Ok(SyntaxHighlighter {
-            ss: SyntaxSet::load_defaults_newlines(),
+            ss: self.syntax_set()?,
             ts,
             theme: self.theme.clone(),
             default_lang: self.default_lang.clone(),
+            style: self.style,
+            strict: self.strict,
         })
     }
+    fn syntax_set(&self) -> Result<Arc<SyntaxSet>, Error> {
+        if self.syntax_dirs.is_empty() {
+            return Ok(DEFAULT_SYNTAXES.clone());
+        }
+        let mut builder = SyntaxSet::clone(&DEFAULT_SYNTAXES).into_builder();
+        for dir in self.syntax_dirs.iter() {
+            builder.add_from_folder(dir, true)?;
+        }
+        Ok(Arc::new(builder.build()))
+    }
+    fn theme_set(&self) -> Result<Arc<ThemeSet>, Error> {
+        if self.theme_dirs.is_empty() {
+            return Ok(DEFAULT_THEMES.clone());
+        }
+        let mut ts = ThemeSet::new();
+        ts.themes = DEFAULT_THEMES.themes.clone();
+        for dir in self.theme_dirs.iter() {
+            ts.add_from_folder(dir)?;
+        }
+        Ok(Arc::new(ts))
+    }
+    /// The names of every theme we can use: the defaults, plus anything in ``theme_dirs``.
+    pub fn available_themes(&self) -> Result<Vec<String>, Error> {
+        let mut themes = self
+            .theme_set()?
+            .themes
+            .keys()
+            .cloned()
+            .collect::<Vec<String>>();
+        themes.sort_unstable();
+        Ok(themes)
+    }
+    /// The CSS that goes with ``HighlightStyle::Classes`` for this theme, or ``None`` for inline styles.
+    pub fn stylesheet(&self) -> Result<Option<String>, Error> {
+        if self.style == HighlightStyle::Inline {
+            return Ok(None);
+        }
+        let ts = self.theme_set()?;
+        let theme = match ts.themes.get(&self.theme) {
+            Some(theme) => theme,
+            None => return Err(Error::MissingSyntaxTheme(self.theme.clone())),
+        };
+        let mut css = css_for_theme_with_class_style(theme, CLASS_STYLE);
+        writeln!(css, ".quizdown-lineno {{ {} }}", LINENO_CSS)?;
+        writeln!(css, ".quizdown-hl {{ {} }}", line_highlight_css(theme))?;
+        Ok(Some(css))
+    }
 }
 
 pub(crate) struct SyntaxHighlighter {
-    ss: SyntaxSet,
-    ts: ThemeSet,
+    ss: Arc<SyntaxSet>,
+    ts: Arc<ThemeSet>,
     theme: String,
     default_lang: String,
+    style: HighlightStyle,
+    strict: bool,
+}
+
+/// Languages that mean "no highlighting", which syntect only knows as "Plain Text".
+const PLAIN_LANGS: &[&str] = &["text", "plain", "plaintext", "txt"];
+
+/// Used when the theme doesn't pick its own ``line_highlight`` color.
+const DEFAULT_LINE_HIGHLIGHT: &str = "#fff8c5";
+const LINENO_CSS: &str = "user-select:none;opacity:0.5;";
+
+fn line_highlight_css(theme: &Theme) -> String {
+    let color = match theme.settings.line_highlight {
+        Some(c) => format!("rgba({},{},{},{:.2})", c.r, c.g, c.b, c.a as f32 / 255.0),
+        None => DEFAULT_LINE_HIGHLIGHT.to_owned(),
+    };
+    format!("display:block;background-color:{};", color)
+}
+
+/// Split highlighted HTML into one string per source line, closing any ``<span>`` still open at the end of a line
+/// and reopening it on the next, so each line can be wrapped on its own.
+fn split_html_lines(html: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut open: Vec<&str> = Vec::new();
+    let mut line = String::new();
+    let mut has_text = false;
+    let mut rest = html;
+    while let Some(c) = rest.chars().next() {
+        if c == '<' {
+            let end = rest.find('>').map(|i| i + 1).unwrap_or_else(|| rest.len());
+            let tag = &rest[..end];
+            if tag.starts_with("</") {
+                open.pop();
+            } else {
+                open.push(tag);
+            }
+            line.push_str(tag);
+            rest = &rest[end..];
+            continue;
+        }
+        rest = &rest[c.len_utf8()..];
+        if c == '\n' {
+            for _ in open.iter() {
+                line.push_str("</span>");
+            }
+            lines.push(std::mem::replace(&mut line, open.concat()));
+            has_text = false;
+        } else {
+            line.push(c);
+            has_text = true;
+        }
+    }
+    if has_text {
+        lines.push(line);
+    }
+    lines
+}
+
+/// The info string of a fenced code block, e.g., ``python {linenos, hl=3-4}``.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct CodeBlockInfo {
+    pub(crate) lang: String,
+    linenos: bool,
+    /// 1-based, inclusive line ranges to emphasize.
+    highlight: Vec<(usize, usize)>,
+}
+
+impl CodeBlockInfo {
+    /// Braces only hold our attributes after a language and when they mention ``linenos`` or ``hl=``; anything else,
+    /// e.g., R Markdown's ``{r}`` or Pandoc's ``{.python}``, is left in the language like before and shows as plain text.
+    /// ``Err`` describes the attribute we couldn't read.
+    pub(crate) fn parse(info: &str, default_lang: &str) -> Result<CodeBlockInfo, String> {
+        let split = info.find('{').and_then(|open| {
+            let close = info.rfind('}').filter(|close| *close > open)?;
+            let (lang, attrs) = (&info[..open], &info[open + 1..close]);
+            let ours = attrs.contains("linenos") || attrs.contains("hl=");
+            if lang.trim().is_empty() || !ours {
+                None
+            } else {
+                Some((lang, attrs))
+            }
+        });
+        let (lang, attrs) = split.unwrap_or((info, ""));
+        let lang = lang.trim();
+        let mut parsed = CodeBlockInfo {
+            lang: if lang.is_empty() { default_lang } else { lang }.to_owned(),
+            ..CodeBlockInfo::default()
+        };
+        // ``hl=1,3-4`` is split by the same commas as the attributes, so bare numbers continue the previous ``hl``.
+        let mut in_hl = false;
+        for attr in attrs.split(',').map(str::trim).filter(|a| !a.is_empty()) {
+            let lines = if let Some(lines) = attr.strip_prefix("hl=") {
+                in_hl = true;
+                lines
+            } else if attr == "linenos" {
+                in_hl = false;
+                parsed.linenos = true;
+                continue;
+            } else if in_hl && attr.starts_with(|c: char| c.is_ascii_digit()) {
+                attr
+            } else {
+                return Err(format!("the attribute {:?}", attr));
+            };
+            let (start, end) = match lines.find('-') {
+                Some(dash) => (lines[..dash].trim(), lines[dash + 1..].trim()),
+                None => (lines, lines),
+            };
+            let bad_range = || format!("the line range {:?}", lines);
+            let range: (usize, usize) = (
+                start.parse().map_err(|_| bad_range())?,
+                end.parse().map_err(|_| bad_range())?,
+            );
+            if range.0 == 0 || range.0 > range.1 {
+                return Err(bad_range());
+            }
+            parsed.highlight.push(range);
+        }
+        Ok(parsed)
+    }
+    fn is_highlighted(&self, line: usize) -> bool {
+        self.highlight
+            .iter()
+            .any(|(start, end)| *start <= line && line <= *end)
+    }
 }
 
 impl SyntaxHighlighter {
-    fn highlight(&self, lang: &str, contents: &str) -> Result<String, Error> {
+    fn find_syntax(&self, lang: &str) -> Result<&SyntaxReference, Error> {
+        if PLAIN_LANGS.contains(&lang.to_lowercase().as_str()) {
+            return Ok(self.ss.find_syntax_plain_text());
+        }
+        match self.ss.find_syntax_by_token(lang) {
+            Some(syntax) => Ok(syntax),
+            None if self.strict => Err(Error::MissingSyntaxLang(lang.to_owned())),
+            None => Ok(self.ss.find_syntax_plain_text()),
+        }
+    }
+    fn highlight(&self, info: &CodeBlockInfo, contents: &str) -> Result<String, Error> {
         let theme = &self.ts.themes[self.theme.as_str()];
-        let syntax_ref = self
-            .ss
-            .find_syntax_by_token(lang)
-            .unwrap_or_else(|| self.ss.find_syntax_plain_text());
-        Ok(highlighted_html_for_string(
-            contents, &self.ss, syntax_ref, theme,
-        ))
+        let syntax_ref = self.find_syntax(&info.lang)?;
+        let plain = !info.linenos && info.highlight.is_empty();
+
+        let (mut output, lines) = match self.style {
+            HighlightStyle::Inline if plain => {
+                return Ok(highlighted_html_for_string(
+                    contents, &self.ss, syntax_ref, theme,
+                ));
+            }
+            HighlightStyle::Inline => {
+                let mut highlighter = HighlightLines::new(syntax_ref, theme);
+                let (pre_start, bg) = start_highlighted_html_snippet(theme);
+                let mut lines = Vec::new();
+                for line in LinesWithEndings::from(contents) {
+                    let regions = highlighter.highlight(line.trim_end_matches('\n'), &self.ss);
+                    let mut line_html = String::new();
+                    append_highlighted_html_for_styled_line(
+                        &regions[..],
+                        IncludeBackground::IfDifferent(bg),
+                        &mut line_html,
+                    );
+                    lines.push(line_html);
+                }
+                (pre_start, lines)
+            }
+            HighlightStyle::Classes => {
+                let mut generator =
+                    ClassedHTMLGenerator::new_with_class_style(syntax_ref, &self.ss, CLASS_STYLE);
+                for line in LinesWithEndings::from(contents) {
+                    generator.parse_html_for_line_which_includes_newline(line);
+                }
+                let code = generator.finalize();
+                let pre_start = "<pre class=\"qd-code\">\n".to_owned();
+                if plain {
+                    return Ok(format!("{}{}</pre>\n", pre_start, code));
+                }
+                (pre_start, split_html_lines(&code))
+            }
+        };
+
+        // Moodle and QTI drop our stylesheet, so inline mode needs inline styles here too.
+        let (hl_style, lineno_style) = match self.style {
+            HighlightStyle::Inline => (
+                format!(" style=\"{}\"", line_highlight_css(theme)),
+                format!(" style=\"{}\"", LINENO_CSS),
+            ),
+            HighlightStyle::Classes => (String::new(), String::new()),
+        };
+        let width = lines.len().to_string().len();
+        for (i, line) in lines.iter().enumerate() {
+            let number = i + 1;
+            let emphasized = info.is_highlighted(number);
+            if emphasized {
+                write!(output, "<span class=\"quizdown-hl\"{}>", hl_style)?;
+            }
+            if info.linenos {
+                write!(
+                    output,
+                    "<span class=\"quizdown-lineno\"{}>{:>width$} </span>",
+                    lineno_style,
+                    number,
+                    width = width
+                )?;
+            }
+            output.push_str(line);
+            // A block-level span already ends the line.
+            output.push_str(if emphasized { "</span>" } else { "\n" });
+        }
+        output.push_str("</pre>\n");
+        Ok(output)
     }
     fn highlight_inline(&self, contents: &str) -> Result<String, Error> {
         let theme = &self.ts.themes[self.theme.as_str()];
-        let syntax_ref = self
-            .ss
-            .find_syntax_by_token(&self.default_lang)
-            .unwrap_or_else(|| self.ss.find_syntax_plain_text());
+        let syntax_ref = self.find_syntax(&self.default_lang)?;
+        if self.style == HighlightStyle::Classes {
+            let mut generator =
+                ClassedHTMLGenerator::new_with_class_style(syntax_ref, &self.ss, CLASS_STYLE);
+            generator.parse_html_for_line_which_includes_newline(contents);
+            return Ok(format!(
+                "<code class=\"qd-code\">{}</code>\n",
+                generator.finalize()
+            ));
+        }
         let mut highlighter = HighlightLines::new(syntax_ref, theme);
         let mut output = String::new();
         let (pre_start, bg) = start_highlighted_html_snippet(theme);
@@ -87,6 +376,74 @@ impl SyntaxHighlighter {
     }
 }
 
+/// Turns the source of a fenced code block into HTML, instead of highlighting it; ``Err`` explains what's wrong with
+/// the source.
+pub type CodeBlockTransform = fn(&str) -> Result<String, String>;
+
+/// Fenced-block languages that are rendered rather than shown as code, until a ``Compiler`` is told otherwise.
+pub(crate) fn default_code_block_transforms() -> HashMap<String, CodeBlockTransform> {
+    let mut transforms: HashMap<String, CodeBlockTransform> = HashMap::new();
+    transforms.insert("dot".to_owned(), diagram::render_dot);
+    transforms.insert("graphviz".to_owned(), diagram::render_dot);
+    transforms
+}
+
+/// Check every code block's attributes, and swap each block in a language with a transform for a single
+/// ``Event::Html`` of its output, keeping the block's span so later errors still point at it.
+pub(crate) fn transform_code_blocks<'md>(
+    markdown: &str,
+    events: Vec<(Event<'md>, Range<usize>)>,
+    transforms: &HashMap<String, CodeBlockTransform>,
+    default_lang: &str,
+) -> Result<Vec<(Event<'md>, Range<usize>)>, Error> {
+    let mut output = Vec::with_capacity(events.len());
+    let mut events = events.into_iter();
+    while let Some((event, range)) = events.next() {
+        let info = match &event {
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))) => info.as_ref(),
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Indented)) => "",
+            _ => {
+                output.push((event, range));
+                continue;
+            }
+        };
+        let info = CodeBlockInfo::parse(info, default_lang).map_err(|found| {
+            let (line, column) = line_column(markdown, range.start);
+            Error::UnexpectedMarkdown {
+                expected: "code block attributes like '{linenos, hl=3-5}'".to_owned(),
+                found,
+                line,
+                column,
+            }
+        })?;
+        let transform = match transforms.get(&info.lang) {
+            Some(transform) => transform,
+            None => {
+                output.push((event, range));
+                continue;
+            }
+        };
+        let mut source = String::new();
+        for (inner, _) in &mut events {
+            match inner {
+                Event::End(Tag::CodeBlock(_)) => break,
+                Event::Text(text) | Event::Html(text) | Event::Code(text) => source.push_str(&text),
+                _ => {}
+            }
+        }
+        let html = transform(&source).map_err(|message| {
+            let (line, column) = line_column(markdown, range.start);
+            Error::DiagramError {
+                message,
+                line,
+                column,
+            }
+        })?;
+        output.push((Event::Html(CowStr::from(html)), range));
+    }
+    Ok(output)
+}
+
 fn syntax_highlight_html<'a>(
     output: &mut String,
     syntax: &SyntaxHighlighter,
@@ -99,31 +456,28 @@ fn syntax_highlight_html<'a>(
         match &events[i] {
             Event::Start(Tag::CodeBlock(kind)) => {
                 current_block_html.clear();
-                let lang: &str = match kind {
-                    CodeBlockKind::Indented => syntax.default_lang.as_ref(),
-                    CodeBlockKind::Fenced(lang) => {
-                        if lang.as_ref() == "" {
-                            syntax.default_lang.as_ref()
-                        } else {
-                            lang.as_ref()
-                        }
-                    }
+                let info = match kind {
+                    CodeBlockKind::Indented => "",
+                    CodeBlockKind::Fenced(info) => info.as_ref(),
                 };
+                let info =
+                    CodeBlockInfo::parse(info, &syntax.default_lang).map_err(Error::Unexpected)?;
                 i += 1;
                 while i < events.len() {
                     match &events[i] {
                         Event::End(Tag::CodeBlock(_)) => break,
-                        Event::Text(line) => {
+                        Event::Text(line) | Event::Html(line) | Event::Code(line) => {
                             current_block_html.push_str(line.as_ref());
                         }
-                        _ => panic!("Bad tag sequence."),
+                        // QParser reports anything else inside a code block with its position; callers that
+                        // skip QParser still get the block's text rather than an error with nowhere to point.
+                        _ => {}
                     }
                     i += 1;
                 }
 
-                with_highlight.push(Event::Html(CowStr::from(
-                    syntax.highlight(lang, &current_block_html)?,
-                )));
+                let block_html = syntax.highlight(&info, &current_block_html)?;
+                with_highlight.push(Event::Html(CowStr::from(block_html)));
             }
             Event::Code(inline) => {
                 with_highlight.push(Event::Html(CowStr::from(syntax.highlight_inline(inline)?)))
@@ -142,6 +496,7 @@ fn syntax_highlight_html<'a>(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testing::TempDir;
     use pulldown_cmark::{Options, Parser};
 
     #[test]
@@ -164,6 +519,104 @@ mod tests {
         assert_eq!("<p>What is the output of <code style=\"background-color:#ffffff;\"><span style=\"color:#323232;\">f1(3)</span></code>\n?</p>\n", html);
     }
 
+    #[test]
+    fn test_linenos_and_hl() {
+        let info = CodeBlockInfo::parse("python {linenos, hl=1,3-4}", "text").unwrap();
+        assert_eq!(
+            CodeBlockInfo {
+                lang: "python".to_owned(),
+                linenos: true,
+                highlight: vec![(1, 1), (3, 4)],
+            },
+            info
+        );
+        assert!(CodeBlockInfo::parse("python {hl=x}", "text").is_err());
+        assert!(CodeBlockInfo::parse("python {linenos, numbers}", "text").is_err());
+        assert!(CodeBlockInfo::parse("python {hl=5-1}", "text").is_err());
+        assert!(CodeBlockInfo::parse("python {hl=0}", "text").is_err());
+        // Other tools' attributes are not ours to reject:
+        for other in &["{r}", "{.python}", "python {numbers}"] {
+            let info = CodeBlockInfo::parse(other, "text").unwrap();
+            assert_eq!(*other, info.lang);
+            assert!(!info.linenos && info.highlight.is_empty());
+        }
+        let example = "```{r}\nx <- 1\n```\n";
+        let tokens = Parser::new(example).collect::<Vec<Event>>();
+        let renderer = SyntaxHighlightingOptions::default().create().unwrap();
+        let mut html = String::new();
+        renderer.render(&mut html, &tokens).unwrap();
+        assert!(html.contains("x &lt;- 1"));
+
+        let example = "```python {linenos, hl=2}\nx = 1\ny = 2\nz = 3\n```\n";
+        let tokens = Parser::new(example).collect::<Vec<Event>>();
+        let renderer = SyntaxHighlightingOptions::default().create().unwrap();
+        let mut html = String::new();
+        renderer.render(&mut html, &tokens).unwrap();
+        assert_eq!(3, html.matches("class=\"quizdown-lineno\"").count());
+        assert_eq!(1, html.matches("class=\"quizdown-hl\"").count());
+        let hl_start = html.find("quizdown-hl").unwrap();
+        assert!(
+            html[hl_start..].find(">2 </span>").unwrap()
+                < html[hl_start..].find(">3 </span>").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_css_classes() {
+        let opts = SyntaxHighlightingOptions {
+            style: HighlightStyle::Classes,
+            ..SyntaxHighlightingOptions::default()
+        };
+        let css = opts.stylesheet().unwrap().unwrap();
+        assert!(css.contains(".qd-code"));
+        assert!(css.contains(".quizdown-hl"));
+        assert!(SyntaxHighlightingOptions::default()
+            .stylesheet()
+            .unwrap()
+            .is_none());
+
+        let example = "```python {hl=2}\ns = \"\"\"a\nb\"\"\"\nx = 1\n```\n";
+        let tokens = Parser::new(example).collect::<Vec<Event>>();
+        let mut html = String::new();
+        opts.create().unwrap().render(&mut html, &tokens).unwrap();
+        assert!(!html.contains("style="));
+        assert!(html.starts_with("<pre class=\"qd-code\">"));
+        // The string spans lines 1-2, so its spans are closed and reopened around the highlighted line.
+        let hl = &html[html.find("<span class=\"quizdown-hl\">").unwrap()..];
+        let hl = &hl[..hl.find("\n").unwrap_or(hl.len())];
+        assert_eq!(hl.matches("<span").count(), hl.matches("</span>").count());
+        assert!(hl.contains("b"));
+    }
+
+    #[test]
+    fn test_custom_syntax_and_strict() {
+        let dir = TempDir::new("syntax");
+        dir.write(
+            "toy.sublime-syntax",
+            b"%YAML 1.2\n---\nname: Toy\nfile_extensions: [toy]\nscope: source.toy\ncontexts:\n  main:\n    - match: '\\bloop\\b'\n      scope: keyword.control.toy\n",
+        );
+        let opts = SyntaxHighlightingOptions {
+            syntax_dirs: vec![dir.to_string_lossy().to_string()],
+            strict: true,
+            ..SyntaxHighlightingOptions::default()
+        };
+        let renderer = opts.create().unwrap();
+
+        let render = |md: &str| {
+            let tokens = Parser::new(md).collect::<Vec<Event>>();
+            let mut html = String::new();
+            renderer.render(&mut html, &tokens).map(|_| html)
+        };
+        assert!(render("```toy\nloop forever\n```\n")
+            .unwrap()
+            .contains("<span style="));
+        assert!(render("```text\nplain\n```\n").is_ok());
+        match render("```kotlin-ish\nfun f() = 1\n```\n").unwrap_err() {
+            Error::MissingSyntaxLang(lang) => assert_eq!("kotlin-ish", lang),
+            other => panic!("Expected MissingSyntaxLang, got {:?}", other),
+        }
+    }
+
     #[test]
     fn test_code_block_events() {
         let example = r#"
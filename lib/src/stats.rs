@@ -0,0 +1,216 @@
+use crate::render::CodeBlockInfo;
+use crate::{Error, Question};
+use pulldown_cmark::{CodeBlockKind, Event, Options, Parser, Tag};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// A summary of a question bank, for ``quizdown stats``.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct Stats {
+    pub questions: usize,
+    /// Every question is worth one point in every export.
+    pub points: usize,
+    /// Questions by kind: ``single-answer``, ``multiple-answer`` or ``no-answer``, by how many options are correct.
+    pub types: BTreeMap<String, usize>,
+    /// Parts of multi-part questions; each also counts as a question.
+    pub parts: usize,
+    /// Questions whose options keep their order (numbered lists) instead of being shuffled.
+    pub ordered: usize,
+    pub average_options: f64,
+    /// How many correct options are at each position: ``A`` for the first option, and so on.
+    /// An author who always puts the answer second shows up here.
+    pub correct_positions: BTreeMap<String, usize>,
+    /// Code blocks by language; blocks without one count as ``default_lang``.
+    pub code_languages: BTreeMap<String, usize>,
+    /// Questions by ``{tag=...}``; a question with several tags counts once under each.
+    pub tags: BTreeMap<String, usize>,
+    pub untagged: usize,
+    /// Questions by ``{difficulty=...}``.
+    pub difficulty: BTreeMap<String, usize>,
+    /// Questions without a ``{difficulty=...}``.
+    pub unrated: usize,
+}
+
+/// ``A``, ``B``, ... ``Z``, then ``27``, ``28``, ...
+pub(crate) fn position_name(index: usize) -> String {
+    if index < 26 {
+        ((b'A' + index as u8) as char).to_string()
+    } else {
+        (index + 1).to_string()
+    }
+}
+
+/// The index ``position_name`` was given, so reports list positions in order; as strings, ``27`` sorts before ``A``.
+fn position_index(name: &str) -> usize {
+    match name.as_bytes() {
+        [letter] if letter.is_ascii_uppercase() => (letter - b'A') as usize,
+        _ => name.parse::<usize>().map(|n| n - 1).unwrap_or(usize::MAX),
+    }
+}
+
+fn count_code_blocks(markdown: &str, default_lang: &str, counts: &mut BTreeMap<String, usize>) {
+    let mut md_opt = Options::empty();
+    md_opt.insert(Options::ENABLE_TABLES);
+    md_opt.insert(Options::ENABLE_TASKLISTS);
+    for event in Parser::new_ext(markdown, md_opt) {
+        let info = match &event {
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Indented)) => "",
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))) => info.as_ref(),
+            _ => continue,
+        };
+        if let Ok(info) = CodeBlockInfo::parse(info, default_lang) {
+            *counts.entry(info.lang).or_default() += 1;
+        }
+    }
+}
+
+impl Stats {
+    /// Summarize ``questions``; ``default_lang`` is the ``SyntaxHighlightingOptions`` one they were read with.
+    pub fn new(questions: &[Question], default_lang: &str) -> Stats {
+        let mut stats = Stats::default();
+        let mut options = 0;
+        for q in questions {
+            stats.questions += 1;
+            stats.points += 1;
+            options += q.options.len();
+            let correct = q.options.iter().filter(|opt| opt.correct).count();
+            let kind = match correct {
+                0 => "no-answer",
+                1 => "single-answer",
+                _ => "multiple-answer",
+            };
+            *stats.types.entry(kind.to_owned()).or_default() += 1;
+            if q.part.is_some() {
+                stats.parts += 1;
+            }
+            if q.ordered {
+                stats.ordered += 1;
+            }
+            for tag in q.tags.iter() {
+                *stats.tags.entry(tag.clone()).or_default() += 1;
+            }
+            if q.tags.is_empty() {
+                stats.untagged += 1;
+            }
+            match &q.difficulty {
+                Some(level) => *stats.difficulty.entry(level.clone()).or_default() += 1,
+                None => stats.unrated += 1,
+            }
+            for (i, opt) in q.options.iter().enumerate() {
+                if opt.correct {
+                    *stats.correct_positions.entry(position_name(i)).or_default() += 1;
+                }
+            }
+            // A multi-part question's stem is shared, so only count its code once:
+            let stem = match q.part {
+                Some(part) if part.index > 0 => "",
+                _ => q.stem_markdown.as_str(),
+            };
+            let markdown = [stem, &q.prompt_markdown, &q.explanation_markdown];
+            for text in markdown
+                .iter()
+                .copied()
+                .chain(q.options.iter().map(|opt| opt.content_markdown.as_str()))
+            {
+                count_code_blocks(text, default_lang, &mut stats.code_languages);
+            }
+        }
+        if stats.questions > 0 {
+            stats.average_options = options as f64 / stats.questions as f64;
+        }
+        stats
+    }
+
+    pub fn to_json(&self) -> Result<String, Error> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// A human-readable report.
+    pub fn to_text(&self) -> String {
+        let mut output = String::new();
+        writeln!(
+            output,
+            "Questions: {} ({} points)",
+            self.questions, self.points
+        )
+        .unwrap();
+        for (kind, count) in self.types.iter() {
+            writeln!(output, "  {}: {}", kind, count).unwrap();
+        }
+        writeln!(output, "  parts of multi-part questions: {}", self.parts).unwrap();
+        writeln!(output, "  with ordered options: {}", self.ordered).unwrap();
+        writeln!(output, "Options per question: {:.1}", self.average_options).unwrap();
+
+        let total: usize = self.correct_positions.values().sum();
+        let mut positions: Vec<(&String, &usize)> = self.correct_positions.iter().collect();
+        positions.sort_by_key(|(name, _)| position_index(name));
+        writeln!(output, "Correct answers by position:").unwrap();
+        for (name, count) in positions {
+            writeln!(
+                output,
+                "  {}: {} ({:.0}%)",
+                name,
+                count,
+                100.0 * *count as f64 / total as f64
+            )
+            .unwrap();
+        }
+
+        writeln!(output, "Code blocks by language:").unwrap();
+        if self.code_languages.is_empty() {
+            writeln!(output, "  (none)").unwrap();
+        }
+        for (lang, count) in self.code_languages.iter() {
+            writeln!(output, "  {}: {}", lang, count).unwrap();
+        }
+
+        writeln!(output, "Questions by tag:").unwrap();
+        for (tag, count) in self.tags.iter() {
+            writeln!(output, "  {}: {}", tag, count).unwrap();
+        }
+        writeln!(output, "  (untagged): {}", self.untagged).unwrap();
+        writeln!(output, "Questions by difficulty:").unwrap();
+        for (level, count) in self.difficulty.iter() {
+            writeln!(output, "  {}: {}", level, count).unwrap();
+        }
+        writeln!(output, "  (unrated): {}", self.unrated).unwrap();
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process_questions_str;
+
+    #[test]
+    fn test_stats() {
+        let qs = process_questions_str(
+            "## A? {tag=loops} {difficulty=easy}\n\n```python\nx = 1\n```\n\n- [ ] 1\n- [x] 2\n- [ ] 3\n\n## B? {tag=loops}\n\n    plain\n\n1. [ ] 1\n2. [x] 2\n\n## C? {tag=io} {tag=loops} {difficulty=hard}\n\n- [x] 1\n- [x] 2\n- [ ] 3\n\n## D?\n\n- [x] 1\n- [ ] 2\n",
+            None,
+        )
+        .unwrap();
+        let stats = Stats::new(&qs, "text");
+        assert_eq!(4, stats.questions);
+        assert_eq!(Some(&3), stats.types.get("single-answer"));
+        assert_eq!(Some(&1), stats.types.get("multiple-answer"));
+        assert_eq!(1, stats.ordered);
+        assert!((stats.average_options - 10.0 / 4.0).abs() < 1e-9);
+        assert_eq!(Some(&2), stats.correct_positions.get("A"));
+        assert_eq!(Some(&3), stats.correct_positions.get("B"));
+        assert_eq!(Some(&1), stats.code_languages.get("python"));
+        assert_eq!(Some(&1), stats.code_languages.get("text"));
+        assert_eq!(Some(&3), stats.tags.get("loops"));
+        assert_eq!(Some(&1), stats.tags.get("io"));
+        assert_eq!(1, stats.untagged);
+        assert_eq!(Some(&1), stats.difficulty.get("hard"));
+        assert_eq!(2, stats.unrated);
+        let text = stats.to_text();
+        assert!(text.contains("  B: 3 (60%)\n"));
+        assert!(text.contains("Questions by tag:\n  io: 1\n  loops: 3\n  (untagged): 1\n"));
+        assert!(text.contains("  easy: 1\n  hard: 1\n  (unrated): 2\n"));
+        let json: serde_json::Value = serde_json::from_str(&stats.to_json().unwrap()).unwrap();
+        assert_eq!(3, json["tags"]["loops"]);
+        assert_eq!(2, json["unrated"]);
+    }
+}
@@ -12,33 +12,86 @@ def default_config() -> Dict[str, Any]:
     return json.loads(lib.default_config())
 
 
-AVAILABLE_FORMATS = ["HtmlSnippet", "HtmlFull", "MoodleXml", "JSON"]
+def json_schema() -> Dict[str, Any]:
+    """
+    The JSON Schema that ``format="JSON"`` output (and ``Quiz.from_dict`` input) follows.
+    """
+    return json.loads(lib.json_schema())
 
 
-def quizdown_render(
-    input: str, name: str = "quizdown", format: str = "MoodleXml", config=None
-) -> str:
-    """
-    Parse some quizdown text into a sequence of questions.
+AVAILABLE_FORMATS = ["HtmlSnippet", "HtmlFull", "HtmlInteractive", "HtmlStandalone", "MoodleXml", "JSON"]
 
-    raises: ValueError
-    """
+
+def _config_str(config) -> str:
     if config is None:
         config = default_config()
-
-    config_str = ""
     if type(config) is dict:
-        config_str = json.dumps(config)
+        return json.dumps(config)
     elif type(config) is str:
-        config_str = config
+        return config
     else:
         raise ValueError(config)
 
+
+def _format_str(format: str) -> str:
     if format not in AVAILABLE_FORMATS:
         raise ValueError(format)
-    of = json.dumps({format: None})
-    return lib.try_parse_quizdown(input, name, of, config_str)
-    
+    return json.dumps({format: None})
+
+
+class Compiler(object):
+    """
+    Renders many inputs with one config.
+
+    raises: ValueError
+    """
+
+    def __init__(self, config=None):
+        self._inner = lib.Compiler(_config_str(config))
+
+    def render(
+        self, input: str, name: str = "quizdown", format: str = "MoodleXml"
+    ) -> str:
+        return self._inner.render(input, name, _format_str(format))
+
+    def render_file(self, path: str, name: str, format: str = "MoodleXml") -> str:
+        """
+        Like render, but local images are found relative to ``path``.
+        """
+        return self._inner.render_file(path, name, _format_str(format))
+
+    def to_py(self, input: str, name: str) -> Quiz:
+        return Quiz.from_dict(json.loads(self.render(input, name, "JSON")))
+
+    def file_to_py(self, path: str, name: str) -> Quiz:
+        return Quiz.from_dict(json.loads(self.render_file(path, name, "JSON")))
+
+
+def quizdown_render(
+    input: str, name: str = "quizdown", format: str = "MoodleXml", config=None
+) -> str:
+    """
+    Parse some quizdown text into a sequence of questions.
+
+    raises: ValueError
+    """
+    return lib.try_parse_quizdown(input, name, _format_str(format), _config_str(config))
+
+
+def html_math_to_mathml(html: str) -> str:
+    """
+    Replace MathJax-style math in rendered HTML with MathML, for consumers without MathJax.
+    """
+    return lib.html_math_to_mathml(html)
+
+
+def quizdown_file_to_py(path: str, name: str, config=None) -> Quiz:
+    """
+    Parse a quizdown file into a Quiz object, loading any local images it references.
+
+    raises: ValueError
+    """
+    return Compiler(config).file_to_py(path, name)
 
 
 def quizdown_to_py(input: str, name: str) -> Quiz:
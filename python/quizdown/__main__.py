@@ -50,7 +50,7 @@ parser.add_argument(
     "--browser",
     action="store_true",
     default=False,
-    help="Directly open a preview in the default web-browser.",
+    help="Deprecated: open the --output HTML file once in the default web-browser. Use 'quizdown serve' instead.",
 )
 
 args = parser.parse_args()
@@ -80,6 +80,13 @@ if not format:
             "Cannot guess format from '{}' for --output '{}'".format(ext, args.output)
         )
 
+if args.browser:
+    print(
+        "--browser is deprecated; `quizdown serve {}` keeps a preview up to date as you edit.".format(
+            args.input
+        ),
+        file=sys.stderr,
+    )
 if args.browser and not args.output:
     raise ValueError("Must specify an --output html file to use --browser.")
 
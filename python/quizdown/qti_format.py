@@ -1,7 +1,16 @@
-from . import quizdown_to_py, default_config, available_themes, AVAILABLE_FORMATS
+from . import (
+    Compiler,
+    default_config,
+    available_themes,
+    html_math_to_mathml,
+    AVAILABLE_FORMATS,
+)
+from .types import replace_srcs
+from urllib.parse import quote
 import zipfile
 import argparse
 import os, json
+import base64
 import uuid
 
 from jinja2 import Template
@@ -25,6 +34,15 @@ def make_id() -> str:
     return str(uuid.uuid4())
 
 
+def media_path(quiz, m) -> str:
+    return "web_resources/{}/{}".format(quiz.uid, m.name)
+
+
+def media_src(quiz, m) -> str:
+    # Canvas resolves this prefix against the root of the zip.
+    return "$IMS-CC-FILEBASE$/{}/{}".format(quote(quiz.uid), quote(m.name))
+
+
 if __name__ == "__main__":
     parser = argparse.ArgumentParser("quizdown_qti", "quizdown_qti MARKDOWN_FILE")
     parser.add_argument(
@@ -54,41 +72,65 @@ if __name__ == "__main__":
         ),
     )
     args = parser.parse_args()
+    config["syntax"]["theme"] = args.theme
+    config["syntax"]["default_lang"] = args.lang
+    compiler = Compiler(config)
     quizzes = []
     for path in args.inputs:
-        with open(path) as fp:
-            raw_input = fp.read()
         base_file = os.path.basename(path)
         name = os.path.splitext(base_file)[0]
-        quiz = quizdown_to_py(raw_input, name)
+        quiz = compiler.file_to_py(path, name)
         # give every quiz, option & question a UUID
         quiz.uid = quiz.name
+        src = lambda m: media_src(quiz, m)
+        if quiz.description:
+            quiz.description = html_math_to_mathml(quiz.description)
         for question in quiz.questions:
             question.uid = make_id()
+            # QTI items stand alone, so every part repeats its stem:
+            question.prompt = html_math_to_mathml(
+                replace_srcs(question.full_prompt(), question.media, src)
+            )
+            question.explanation = html_math_to_mathml(
+                replace_srcs(question.explanation, question.media, src)
+            )
             for opt in question.options:
                 opt.uid = make_id()
+                opt.content = html_math_to_mathml(
+                    replace_srcs(opt.content, opt.media, src)
+                )
         quizzes.append(quiz)
 
     with zipfile.ZipFile("output.qti.zip", "w") as zf:
         zf.writestr(
             "imsmanifest.xml",
             QTI_MANIFEST_TMPL.render(
-                ident="TBD", title="Quizdown Import TODO", quizzes=quizzes
+                ident="TBD",
+                title="Quizdown Import TODO",
+                quizzes=quizzes,
+                media=[
+                    (make_id(), media_path(q, m)) for q in quizzes for m in q.media()
+                ],
             ),
         )
 
         for q in quizzes:
 
-            quiz_xml = QTI_QUIZ_TMPL.render(quiz=q, title=q.name, questions=q.questions)
+            quiz_xml = QTI_QUIZ_TMPL.render(
+                quiz=q, title=q.title or q.name, questions=q.questions
+            )
             quiz_zip_path = "{0}/{0}.xml".format(q.uid)
             zf.writestr(quiz_zip_path, quiz_xml)
 
             meta_zip_path = "{0}/assessment_meta.xml".format(q.uid)
             meta_xml = QTI_QUIZ_META_TMPL.render(
                 quiz=q,
-                title=q.name.replace("_", " "),
+                title=q.title or q.name.replace("_", " "),
                 points_possible=float(len(q.questions)),
                 assignment_identifier=make_id(),
             )
             zf.writestr(meta_zip_path, meta_xml)
 
+            for m in q.media():
+                zf.writestr(media_path(q, m), base64.b64decode(m.base64))
+
@@ -1,16 +1,56 @@
+"""
+Python mirrors of the quiz JSON; ``quizdown.json_schema()`` is the authoritative description of its shape.
+"""
 import attr
 from typing import List, Dict, Any, Optional
 
 
+@attr.s
+class MediaFile(object):
+    src: str = attr.ib()
+    name: str = attr.ib()
+    mime: str = attr.ib()
+    base64: str = attr.ib()
+
+    @staticmethod
+    def from_dict(d: Dict[str, Any]) -> "MediaFile":
+        return MediaFile(d["src"], d["name"], d["mime"], d["base64"])
+
+
+def replace_srcs(html: str, media: List[MediaFile], new_src) -> str:
+    for m in media:
+        html = html.replace('<img src="{}"'.format(m.src), '<img src="{}"'.format(new_src(m)))
+    return html
+
+
 @attr.s
 class QOption(object):
     correct: bool = attr.ib()
     content: str = attr.ib()
+    media: List[MediaFile] = attr.ib(factory=list)
+    content_markdown: str = attr.ib(default="")
+    pinned: bool = attr.ib(default=False)
     uid: Optional[str] = attr.ib(default=None)
 
     @staticmethod
     def from_dict(d: Dict[str, Any]) -> "QOption":
-        return QOption(d["correct"], d["content"])
+        return QOption(
+            d["correct"],
+            d["content"],
+            [MediaFile.from_dict(m) for m in d.get("media", [])],
+            d.get("content_markdown", ""),
+            d.get("pinned", False),
+        )
+
+
+@attr.s
+class Part(object):
+    index: int = attr.ib()
+    count: int = attr.ib()
+
+    @staticmethod
+    def from_dict(d: Dict[str, Any]) -> "Part":
+        return Part(d["index"], d["count"])
 
 
 @attr.s
@@ -18,14 +58,38 @@ class Question(object):
     prompt: str = attr.ib()
     ordered: bool = attr.ib()
     options: List[QOption] = attr.ib(factory=list)
+    media: List[MediaFile] = attr.ib(factory=list)
+    prompt_markdown: str = attr.ib(default="")
+    explanation: str = attr.ib(default="")
+    explanation_markdown: str = attr.ib(default="")
+    stem: str = attr.ib(default="")
+    stem_markdown: str = attr.ib(default="")
+    part: Optional[Part] = attr.ib(default=None)
+    title: str = attr.ib(default="")
+    reorderable: bool = attr.ib(default=False)
     uid: Optional[str] = attr.ib(default=None)
 
     @staticmethod
     def from_dict(d: Dict[str, Any]) -> "Question":
         return Question(
-            d["prompt"], d["ordered"], [QOption.from_dict(opt) for opt in d["options"]]
+            d["prompt"],
+            d.get("ordered", False),
+            [QOption.from_dict(opt) for opt in d["options"]],
+            [MediaFile.from_dict(m) for m in d.get("media", [])],
+            d.get("prompt_markdown", ""),
+            d.get("explanation", ""),
+            d.get("explanation_markdown", ""),
+            d.get("stem", ""),
+            d.get("stem_markdown", ""),
+            Part.from_dict(d["part"]) if d.get("part") else None,
+            d.get("title", ""),
+            d.get("reorderable", False),
         )
 
+    def full_prompt(self) -> str:
+        """The shared stem of a multi-part question, then this part's prompt."""
+        return self.stem + self.prompt
+
     def option_uids(self) -> List[str]:
         return [opt.uid for opt in self.options if opt.uid]
 
@@ -35,10 +99,27 @@ class Quiz(object):
     name = attr.ib()
     questions: List[Question] = attr.ib(factory=list)
     uid: Optional[str] = attr.ib(default=None)
+    title: Optional[str] = attr.ib(default=None)
+    time_limit_minutes: Optional[int] = attr.ib(default=None)
+    description: Optional[str] = attr.ib(default=None)
 
     def meta_id(self) -> str:
         return "meta:{}".format(self.uid)
 
+    def media(self) -> List[MediaFile]:
+        """Every image in the quiz, once each; ``name`` already tells different files apart."""
+        found: Dict[str, MediaFile] = {}
+        for q in self.questions:
+            for m in q.media + [m for opt in q.options for m in opt.media]:
+                found.setdefault(m.name, m)
+        return list(found.values())
+
     @staticmethod
     def from_dict(d: Dict[str, Any]) -> "Quiz":
-        return Quiz(d["name"], [Question.from_dict(q) for q in d["questions"]])
+        return Quiz(
+            d["name"],
+            [Question.from_dict(q) for s in d["sections"] for q in s["questions"]],
+            title=d.get("title"),
+            time_limit_minutes=d.get("time_limit_minutes"),
+            description=d.get("description"),
+        )
@@ -15,6 +15,18 @@ pub fn available_themes() -> PyResult<Vec<String>> {
     Ok(qd::list_themes())
 }
 
+/// A JSON Schema for the ``JSON`` output format.
+#[pyfunction]
+pub fn json_schema() -> PyResult<String> {
+    Ok(qd::json_schema())
+}
+
+/// QTI consumers don't load MathJax, so exporters convert ``\(...\)`` math to MathML.
+#[pyfunction]
+pub fn html_math_to_mathml(html: &str) -> PyResult<String> {
+    Ok(qd::mathml::html_math_to_mathml(html))
+}
+
 fn stringify_err<E: std::error::Error>(context: &str, e: E) -> PyErr {
     PyValueError::new_err(format!("{}: {:?}", context, e))
 }
@@ -25,17 +37,61 @@ pub fn try_parse_quizdown(text: &str, name: &str, format: &str, config: &str) ->
         serde_json::from_str(format).map_err(|e| stringify_err("format invalid", e))?;
     let config: qd::Config =
         serde_json::from_str(config).map_err(|e| stringify_err("config invalid", e))?;
-    let parsed = qd::process_questions_str(text, Some(config))
+    let quiz = qd::process_quiz_str(text, name, Some(config))
         .map_err(|e| stringify_err("Parsing Error", e))?;
     Ok(format
-        .render(name, &parsed)
+        .render(&quiz)
         .map_err(|e| stringify_err("Rendering Error", e))?)
 }
 
+/// Wraps ``quizdown_lib::Compiler``: one config, many inputs.
+#[pyclass]
+pub struct Compiler {
+    inner: qd::Compiler,
+}
+
+#[pymethods]
+impl Compiler {
+    #[new]
+    fn new(config: &str) -> PyResult<Self> {
+        let config: qd::Config =
+            serde_json::from_str(config).map_err(|e| stringify_err("config invalid", e))?;
+        let inner = qd::Compiler::new(config).map_err(|e| stringify_err("config invalid", e))?;
+        Ok(Compiler { inner })
+    }
+
+    fn render(&self, text: &str, name: &str, format: &str) -> PyResult<String> {
+        let format: qd::OutputFormat =
+            serde_json::from_str(format).map_err(|e| stringify_err("format invalid", e))?;
+        let quiz = self
+            .inner
+            .quiz_from_str(name, text)
+            .map_err(|e| stringify_err("Parsing Error", e))?;
+        Ok(format
+            .render(&quiz)
+            .map_err(|e| stringify_err("Rendering Error", e))?)
+    }
+
+    fn render_file(&self, path: &str, name: &str, format: &str) -> PyResult<String> {
+        let format: qd::OutputFormat =
+            serde_json::from_str(format).map_err(|e| stringify_err("format invalid", e))?;
+        let quiz = self
+            .inner
+            .quiz_from_file(path, Some(name))
+            .map_err(|e| stringify_err("Parsing Error", e))?;
+        Ok(format
+            .render(&quiz)
+            .map_err(|e| stringify_err("Rendering Error", e))?)
+    }
+}
+
 #[pymodule]
 pub fn quizdown(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(available_themes, m)?)?;
     m.add_function(wrap_pyfunction!(default_config, m)?)?;
     m.add_function(wrap_pyfunction!(try_parse_quizdown, m)?)?;
+    m.add_function(wrap_pyfunction!(html_math_to_mathml, m)?)?;
+    m.add_function(wrap_pyfunction!(json_schema, m)?)?;
+    m.add_class::<Compiler>()?;
     Ok(())
 }
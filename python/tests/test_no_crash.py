@@ -2,6 +2,8 @@ import unittest
 
 from quizdown import *
 import bs4
+import json
+import jsonschema
 from bs4 import BeautifulSoup
 
 EXAMPLE = """
@@ -39,3 +41,16 @@ class TestNoCrash(unittest.TestCase):
                 found = True
                 self.assertEqual(lbl.text.strip(), "red")
         self.assertTrue(found)
+
+    def test_compiler_reuse(self):
+        compiler = Compiler()
+        first = compiler.render(EXAMPLE, format="HtmlSnippet")
+        second = compiler.render(EXAMPLE, format="HtmlSnippet")
+        self.assertEqual(first, second)
+        self.assertEqual(first, quizdown_render(EXAMPLE, format="HtmlSnippet"))
+
+    def test_json_schema(self):
+        output = json.loads(quizdown_render(EXAMPLE, format="JSON"))
+        jsonschema.validate(output, json_schema())
+        quiz = Quiz.from_dict(output)
+        self.assertEqual(3, len(quiz.questions[0].options))
@@ -1,4 +1,4 @@
-use quizdown_lib::{process_questions_str, Config, OutputFormat};
+use quizdown_lib::{process_quiz_str, Config, OutputFormat};
 use wasm_bindgen::prelude::*;
 
 #[wasm_bindgen]
@@ -6,6 +6,12 @@ pub fn default_config() -> JsValue {
     JsValue::from_serde(&Config::default()).unwrap()
 }
 
+/// A JSON Schema for the ``JSON`` output format.
+#[wasm_bindgen]
+pub fn json_schema() -> String {
+    quizdown_lib::json_schema()
+}
+
 #[wasm_bindgen]
 pub fn render_questions(
     text: &str,
@@ -13,11 +19,47 @@ pub fn render_questions(
     format: JsValue,
     config: JsValue,
 ) -> Result<String, JsValue> {
-    let format: OutputFormat = format.into_serde().unwrap();
-    let config: Config = config.into_serde().unwrap();
-    let parsed =
-        process_questions_str(text, Some(config)).map_err(|e| format!("Parsing Error: {}", e))?;
+    let format: OutputFormat = format
+        .into_serde()
+        .map_err(|e| format!("Format Error: {}", e))?;
+    let config: Config = config
+        .into_serde()
+        .map_err(|e| format!("Config Error: {}", e))?;
+    let quiz =
+        process_quiz_str(text, name, Some(config)).map_err(|e| format!("Parsing Error: {}", e))?;
     Ok(format
-        .render(name, &parsed)
+        .render(&quiz)
         .map_err(|e| format!("Rendering Error: {}", e))?)
 }
+
+/// A ``quizdown_lib::Compiler`` for the live editor: build one per config and re-use it on every keystroke.
+#[wasm_bindgen]
+pub struct Compiler {
+    inner: quizdown_lib::Compiler,
+}
+
+#[wasm_bindgen]
+impl Compiler {
+    #[wasm_bindgen(constructor)]
+    pub fn new(config: JsValue) -> Result<Compiler, JsValue> {
+        let config: Config = config
+            .into_serde()
+            .map_err(|e| format!("Config Error: {}", e))?;
+        let inner =
+            quizdown_lib::Compiler::new(config).map_err(|e| format!("Config Error: {}", e))?;
+        Ok(Compiler { inner })
+    }
+
+    pub fn render(&self, text: &str, name: &str, format: JsValue) -> Result<String, JsValue> {
+        let format: OutputFormat = format
+            .into_serde()
+            .map_err(|e| format!("Format Error: {}", e))?;
+        let quiz = self
+            .inner
+            .quiz_from_str(name, text)
+            .map_err(|e| format!("Parsing Error: {}", e))?;
+        Ok(format
+            .render(&quiz)
+            .map_err(|e| format!("Rendering Error: {}", e))?)
+    }
+}